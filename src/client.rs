@@ -1,29 +1,49 @@
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::env;
 use std::os::unix::net::UnixStream;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ExitStatus};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, Signal, SignalKind};
 use tracing::warn;
 
 use crate::log_info;
 
-use crate::config::Config;
+use crate::config::{full_domain_name, Config};
 use crate::detect::{detect, PortStrategy};
-use crate::process::spawn_app;
-use crate::types::{pid_path, socket_path, Request, Response};
+use crate::logs::{log_line, unix_millis};
+use crate::process::{self, app_command, spawn_app};
+use crate::types::{
+    pid_path, socket_path, ListenConfig, LogLine, LogStream, Request, Response, RestartPolicy,
+};
+
+/// How long to wait for an exited app's last output to reach the daemon
+const LOG_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Open a connection to the daemon's control socket
+fn connect() -> Result<BufReader<UnixStream>> {
+    let stream = UnixStream::connect(socket_path()).context(
+        "Could not connect to daemon. Is it running? Start it with: unport daemon",
+    )?;
+    Ok(BufReader::new(stream))
+}
 
 /// Send a request to the daemon and get a response
 fn send_request(request: &Request) -> Result<Response> {
-    let socket = socket_path();
-    let mut stream = UnixStream::connect(&socket).context(
-        "Could not connect to daemon. Is it running? Start it with: unport daemon",
-    )?;
+    send_on(&mut connect()?, request)
+}
 
+/// Send a request over an open connection and read the response
+fn send_on(conn: &mut BufReader<UnixStream>, request: &Request) -> Result<Response> {
     let request_json = serde_json::to_string(request)? + "\n";
-    stream.write_all(request_json.as_bytes())?;
+    conn.get_mut().write_all(request_json.as_bytes())?;
 
-    let mut reader = BufReader::new(stream);
     let mut line = String::new();
-    reader.read_line(&mut line)?;
+    conn.read_line(&mut line)?;
 
     // Handle empty response (e.g., daemon closed connection after shutdown)
     if line.is_empty() {
@@ -35,13 +55,21 @@ fn send_request(request: &Request) -> Result<Response> {
 }
 
 /// Start an app and register with daemon
-pub async fn start() -> Result<()> {
+pub async fn start(detach: bool) -> Result<()> {
     let cwd = env::current_dir()?;
 
     // Load config
     let config = Config::load(&cwd)?;
     let domain = config.full_domain();
 
+    // Only the daemon restarts services, and it only owns background ones
+    let options = config.service_options();
+    if !detach && options.restart != RestartPolicy::Never {
+        anyhow::bail!(
+            "\"restart\" in unport.json only works for background services; start with: unport start -d"
+        );
+    }
+
     // Detect framework
     let detection = detect(&cwd)?;
     log_info!("Detected framework: {}", detection.framework);
@@ -50,7 +78,11 @@ pub async fn start() -> Result<()> {
     let start_command = config.start.as_deref().unwrap_or(&detection.start_command);
 
     // Get port from daemon
-    let port = match send_request(&Request::GetPort)? {
+    let port = match send_request(&Request::GetPort {
+        domain: domain.clone(),
+        directory: cwd.clone(),
+        port: config.port,
+    })? {
         Response::Port(p) => p,
         Response::Error(e) => anyhow::bail!("{}", e),
         _ => anyhow::bail!("Unexpected response from daemon"),
@@ -67,27 +99,55 @@ pub async fn start() -> Result<()> {
         detection.port_strategy
     };
 
-    // Check if HTTPS is enabled
-    let https_enabled = match send_request(&Request::HttpsStatus) {
-        Ok(Response::HttpsEnabled(enabled)) => enabled,
-        _ => false,
-    };
+    // Find out where the proxy listens (and whether HTTPS is enabled)
+    let listen = daemon_listeners();
+    let https_url = listen.https_url(&domain);
 
-    if https_enabled {
-        log_info!("HTTPS enabled, certificate updated for https://{}", domain);
+    if let Some(url) = &https_url {
+        log_info!("HTTPS enabled, certificate updated for {}", url);
     }
 
     // Spawn the app
     println!("Starting {}...", config.domain);
     println!("Running: {} (port {})", start_command, port);
-    if https_enabled {
-        println!("Available at: http://{}", domain);
-        println!("              https://{}", domain);
-    } else {
-        println!("Available at: http://{}", domain);
+    println!("Available at: {}", listen.http_url(&domain));
+    if let Some(url) = &https_url {
+        println!("              {}", url);
     }
     println!();
 
+    if detach {
+        // Hand the process to the daemon so it outlives this terminal
+        let mut command = app_command(
+            start_command,
+            port,
+            &port_strategy,
+            config.port_env.as_deref(),
+            config.port_arg.as_deref(),
+        )?;
+        let mut env: BTreeMap<String, String> = env::vars().collect();
+        env.append(&mut command.env);
+        command.env = env;
+
+        return match send_request(&Request::Spawn {
+            domain: domain.clone(),
+            port,
+            directory: cwd,
+            command,
+            options,
+        })? {
+            Response::Ok(message) => {
+                println!("{}", message.unwrap_or_else(|| format!("Started {}", domain)));
+                println!("Running in the background. Stop it with: unport stop {}", config.domain);
+                Ok(())
+            }
+            Response::Error(e) => anyhow::bail!("{}", e),
+            _ => anyhow::bail!("Unexpected response from daemon"),
+        };
+    }
+
+    // Installed before the app starts, so an early Ctrl+C can't orphan it
+    let mut signals = ForwardedSignals::install()?;
     let mut child = spawn_app(
         start_command,
         port,
@@ -97,6 +157,7 @@ pub async fn start() -> Result<()> {
     )?;
 
     let pid = child.id();
+    let output = echo_output(&mut child);
 
     // Register with daemon
     match send_request(&Request::Register {
@@ -104,6 +165,7 @@ pub async fn start() -> Result<()> {
         port,
         pid,
         directory: cwd,
+        options,
     })? {
         Response::Ok(_) => {}
         Response::Error(e) => {
@@ -111,30 +173,69 @@ pub async fn start() -> Result<()> {
         }
         _ => {}
     }
-
-    // Set up Ctrl+C handler
-    let domain_clone = domain.clone();
-    ctrlc::set_handler(move || {
-        // Unregister on exit
-        let _ = send_request(&Request::Unregister {
-            domain: domain_clone.clone(),
-        });
-        std::process::exit(0);
-    })?;
-
-    // Wait for child to exit
-    let status = child.wait()?;
+    let forwarded = forward_logs(domain.clone(), output);
+
+    // Wait for child to exit, letting it handle Ctrl+C and friends itself
+    let waiter = tokio::task::spawn_blocking(move || child.wait());
+    tokio::pin!(waiter);
+    let status = loop {
+        tokio::select! {
+            status = &mut waiter => break status??,
+            signal = signals.recv() => {
+                // The app has its own process group, so the terminal doesn't reach it
+                process::signal_service(pid, signal);
+            }
+        }
+    };
+    let _ = forwarded.recv_timeout(LOG_FLUSH_TIMEOUT);
 
     // Unregister
     let _ = send_request(&Request::Unregister { domain });
 
-    if status.success() {
-        Ok(())
-    } else {
-        anyhow::bail!("Process exited with status: {}", status)
+    if !status.success() {
+        if let Some(code) = status.code() {
+            eprintln!("Process exited with status {}", code);
+        }
+        std::process::exit(exit_code(status));
+    }
+    Ok(())
+}
+
+/// Signals `unport start` passes on to the app instead of exiting on
+struct ForwardedSignals {
+    interrupt: Signal,
+    terminate: Signal,
+    hangup: Signal,
+}
+
+impl ForwardedSignals {
+    fn install() -> Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+            hangup: signal(SignalKind::hangup())?,
+        })
+    }
+
+    /// Wait for the next signal and return its number
+    async fn recv(&mut self) -> i32 {
+        tokio::select! {
+            _ = self.interrupt.recv() => libc::SIGINT,
+            _ = self.terminate.recv() => libc::SIGTERM,
+            _ = self.hangup.recv() => libc::SIGHUP,
+        }
     }
 }
 
+/// Exit code for `unport start` mirroring the app's, using the shell's
+/// 128 + N convention for an app killed by signal N
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
 /// List all registered services
 pub async fn list() -> Result<()> {
     let response = send_request(&Request::List)?;
@@ -144,24 +245,33 @@ pub async fn list() -> Result<()> {
             if services.is_empty() {
                 println!("No services registered.");
             } else {
-                println!(
-                    "{:<24} {:<8} {:<8} {}",
-                    "DOMAIN", "PORT", "PID", "DIRECTORY"
-                );
+                let listen = daemon_listeners();
+                println!("{:<32} {:<8} {:<8} DIRECTORY", "URL", "PORT", "PID");
                 for service in services {
-                    let status = if is_process_alive(service.pid) {
-                        ""
+                    let status = if !process::is_service_alive(&service) {
+                        " (dead)".to_string()
+                    } else if let Some(health) = service.health {
+                        format!(" ({})", health.as_str())
                     } else {
-                        " (dead)"
+                        String::new()
                     };
                     println!(
-                        "{:<24} {:<8} {:<8} {}{}",
-                        service.domain,
+                        "{:<32} {:<8} {:<8} {}{}",
+                        listen.http_url(&service.domain),
                         service.port,
                         service.pid,
                         service.directory.display(),
                         status
                     );
+                    if service.options.wildcard {
+                        println!("  *.{}", service.domain);
+                    }
+                    for route in &service.options.routes {
+                        println!("  {}", route);
+                    }
+                    if let Some(summary) = service.exit_summary() {
+                        println!("  {}", summary);
+                    }
                 }
             }
         }
@@ -176,13 +286,129 @@ pub async fn list() -> Result<()> {
     Ok(())
 }
 
+/// Pass an app's output through to this terminal, collecting it line by line
+fn echo_output(child: &mut Child) -> mpsc::Receiver<LogLine> {
+    let (tx, rx) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        echo_stream(stdout, std::io::stdout(), LogStream::Stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        echo_stream(stderr, std::io::stderr(), LogStream::Stderr, tx);
+    }
+    rx
+}
+
+fn echo_stream<R, W>(mut output: R, mut echo: W, stream: LogStream, lines: mpsc::Sender<LogLine>)
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        let mut line = Vec::new();
+        loop {
+            let n = match output.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            // Echo right away, so prompts and progress bars still show up
+            let _ = echo.write_all(&buf[..n]);
+            let _ = echo.flush();
+
+            for chunk in buf[..n].split_inclusive(|&b| b == b'\n') {
+                line.extend_from_slice(chunk);
+                if line.ends_with(b"\n") {
+                    let _ = lines.send(log_line(stream, &line));
+                    line.clear();
+                }
+            }
+        }
+        if !line.is_empty() {
+            let _ = lines.send(log_line(stream, &line));
+        }
+    });
+}
+
+/// Send collected output to the daemon's log for the service
+///
+/// The returned receiver fires once the app's output has closed and
+/// everything was sent.
+fn forward_logs(domain: String, lines: mpsc::Receiver<LogLine>) -> mpsc::Receiver<()> {
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut conn = None;
+        // Wait for a line, then send it along with whatever else is queued
+        while let Ok(first) = lines.recv() {
+            let mut batch = vec![first];
+            batch.extend(lines.try_iter());
+
+            if conn.is_none() {
+                conn = connect().ok();
+            }
+            if let Some(c) = &mut conn {
+                let request = Request::AppendLogs {
+                    domain: domain.clone(),
+                    lines: batch,
+                };
+                if send_on(c, &request).is_err() {
+                    conn = None;
+                }
+            }
+        }
+        let _ = done_tx.send(());
+    });
+    done_rx
+}
+
+/// Print a service's output, optionally following new lines
+pub async fn logs(domain: &str, follow: bool, since: Option<Duration>) -> Result<()> {
+    let since = since.map(|ago| unix_millis(SystemTime::now()).saturating_sub(ago.as_millis() as u64));
+    let mut conn = connect()?;
+    let mut response = send_on(
+        &mut conn,
+        &Request::Logs {
+            domain: full_domain_name(domain),
+            follow,
+            since,
+        },
+    )?;
+
+    loop {
+        match response {
+            Response::Logs(lines) => {
+                for line in lines {
+                    match line.stream {
+                        LogStream::Stdout => println!("{}", line.text),
+                        LogStream::Stderr => eprintln!("{}", line.text),
+                    }
+                }
+            }
+            Response::Error(e) => anyhow::bail!("{}", e),
+            _ => anyhow::bail!("Unexpected response"),
+        }
+        if !follow {
+            return Ok(());
+        }
+
+        let mut json = String::new();
+        if conn.read_line(&mut json)? == 0 {
+            return Ok(());
+        }
+        response = serde_json::from_str(&json)?;
+    }
+}
+
+/// Ask the daemon where its proxy listens, assuming the defaults if it can't say
+fn daemon_listeners() -> ListenConfig {
+    match send_request(&Request::Listeners) {
+        Ok(Response::Listeners(listen)) => listen,
+        _ => ListenConfig::default(),
+    }
+}
+
 /// Stop a service by domain
 pub async fn stop_service(domain: &str) -> Result<()> {
-    let full_domain = if domain.contains('.') {
-        domain.to_string()
-    } else {
-        format!("{}.localhost", domain)
-    };
+    let full_domain = full_domain_name(domain);
 
     let response = send_request(&Request::Stop { domain: full_domain })?;
 
@@ -218,6 +444,18 @@ pub async fn stop_daemon() -> Result<()> {
     Ok(())
 }
 
+/// Make the daemon re-read its config file
+pub async fn reload_daemon() -> Result<()> {
+    match send_request(&Request::Reload)? {
+        Response::Ok(message) => {
+            println!("{}", message.unwrap_or_else(|| "Daemon reloaded.".to_string()));
+            Ok(())
+        }
+        Response::Error(e) => anyhow::bail!("{}", e),
+        _ => anyhow::bail!("Unexpected response from daemon"),
+    }
+}
+
 /// Show daemon status
 pub async fn daemon_status() -> Result<()> {
     let pid_file = pid_path();
@@ -234,7 +472,7 @@ pub async fn daemon_status() -> Result<()> {
     let pid: u32 = pid_str.trim().parse().context("Invalid PID file")?;
 
     // Check if process is alive
-    if !is_process_alive(pid) {
+    if !process::is_alive(pid) {
         println!("Status: stopped");
         println!("  Daemon is not running (stale PID file, process {} not found)", pid);
         return Ok(());
@@ -287,16 +525,6 @@ fn format_duration(duration: std::time::Duration) -> String {
     }
 }
 
-fn is_process_alive(pid: u32) -> bool {
-    let result = unsafe { libc::kill(pid as i32, 0) };
-    if result == 0 {
-        return true;
-    }
-    // EPERM means process exists but we can't signal it (e.g., root-owned process)
-    let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
-    errno == libc::EPERM
-}
-
 /// Add or remove the CA certificate from the system trust store
 pub async fn trust_ca(remove: bool) -> Result<()> {
     crate::tls::trust_ca(remove)
@@ -309,7 +537,7 @@ pub async fn regen_cert() -> Result<()> {
     // Get list of registered services from daemon
     let domains: Vec<String> = match send_request(&crate::types::Request::List) {
         Ok(Response::Services(services)) => {
-            services.into_iter().map(|s| s.domain).collect()
+            services.iter().flat_map(|s| s.cert_names()).collect()
         }
         Ok(_) => vec![],
         Err(_) => {
@@ -361,20 +589,11 @@ mod tests {
     }
 
     #[test]
-    fn test_is_process_alive_current() {
-        let pid = std::process::id();
-        assert!(is_process_alive(pid));
-    }
-
-    #[test]
-    fn test_is_process_alive_dead() {
-        // Very high PID that doesn't exist
-        assert!(!is_process_alive(4000000));
-    }
-
-    #[test]
-    fn test_is_process_alive_init() {
-        // PID 1 should always exist (init/systemd)
-        assert!(is_process_alive(1));
+    fn test_exit_code() {
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        // Exited with code 3
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        // Killed by SIGTERM
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGTERM)), 143);
     }
 }
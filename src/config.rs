@@ -1,6 +1,13 @@
 use anyhow::{Context, Result};
 use serde::Deserialize;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::time::Duration;
+
+use crate::types::{
+    listen_config, parse_http_addr, parse_https_addr, HealthCheck, ListenArgs, ListenConfig,
+    RestartPolicy, RouteRule, ServiceOptions, UpstreamProtocol, PORT_RANGE_END, PORT_RANGE_START,
+};
 
 /// Configuration from unport.json
 #[derive(Debug, Deserialize)]
@@ -18,6 +25,48 @@ pub struct Config {
     /// Optional: CLI argument for port (e.g., "--port")
     #[serde(rename = "portArg")]
     pub port_arg: Option<String>,
+
+    /// Optional: Always use this port instead of one picked by the daemon
+    pub port: Option<u16>,
+
+    /// Optional: Protocol for talking to the app ("http1", "h2c" or "auto")
+    pub upstream: Option<UpstreamProtocol>,
+
+    /// Optional: Set to false to skip X-Forwarded-* / Forwarded headers (default: true)
+    #[serde(rename = "forwardedHeaders")]
+    pub forwarded_headers: Option<bool>,
+
+    /// Optional: Path prefixes served by other services (e.g., "/api" -> "api")
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+
+    /// Optional: Also serve subdomains like tenant.myapp.localhost (default: false)
+    #[serde(default)]
+    pub wildcard: bool,
+
+    /// Optional: Restart policy for background services ("never", "on-failure", "always")
+    pub restart: Option<RestartPolicy>,
+
+    /// Optional: Restarts in a row before giving up (default: 5)
+    #[serde(rename = "maxRetries")]
+    pub max_retries: Option<u32>,
+
+    /// Optional: HTTP probe that tells when the app is ready and stays healthy
+    #[serde(rename = "healthCheck")]
+    pub health_check: Option<HealthCheck>,
+
+    /// Optional: Seconds to hold requests while the app starts listening (default: 30)
+    #[serde(rename = "startTimeout")]
+    pub start_timeout: Option<u64>,
+
+    /// Optional: Seconds to wait after SIGTERM before killing the app (default: 10)
+    #[serde(rename = "stopTimeout")]
+    pub stop_timeout: Option<u64>,
+
+    /// Optional: Route to the port the app really listens on if it ignores the
+    /// assigned one (default: false)
+    #[serde(rename = "autoPort", default)]
+    pub auto_port: bool,
 }
 
 impl Config {
@@ -38,4 +87,185 @@ impl Config {
     pub fn full_domain(&self) -> String {
         format!("{}.localhost", self.domain)
     }
+
+    /// Per-service proxy settings to send to the daemon
+    pub fn service_options(&self) -> ServiceOptions {
+        let defaults = ServiceOptions::default();
+        ServiceOptions {
+            upstream: self.upstream.unwrap_or_default(),
+            forwarded_headers: self.forwarded_headers.unwrap_or(true),
+            routes: self
+                .routes
+                .iter()
+                .map(|route| RouteRule {
+                    path: normalize_route_path(&route.path),
+                    service: full_domain_name(&route.service),
+                    strip_prefix: route.strip_prefix,
+                })
+                .collect(),
+            wildcard: self.wildcard,
+            restart: self.restart.unwrap_or_default(),
+            max_retries: self.max_retries.unwrap_or(defaults.max_retries),
+            health_check: self.health_check.clone().map(|check| HealthCheck {
+                path: format!("/{}", check.path.trim_start_matches('/')),
+                ..check
+            }),
+            start_timeout: self.start_timeout.unwrap_or(defaults.start_timeout),
+            stop_timeout: self.stop_timeout.unwrap_or(defaults.stop_timeout),
+            auto_port: self.auto_port,
+        }
+    }
+}
+
+/// Expand a short service name to its domain ("api" becomes "api.localhost")
+pub fn full_domain_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.localhost", name)
+    }
+}
+
+/// Give a route prefix a leading slash and no trailing one ("api/" becomes "/api")
+fn normalize_route_path(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+/// Daemon settings from ~/.unport/config.json
+///
+/// Unknown keys are errors, so a misspelled setting isn't silently ignored.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// First port handed out to apps (default: 4000)
+    #[serde(rename = "portRangeStart", default = "default_port_range_start")]
+    pub port_range_start: u16,
+
+    /// Last port handed out to apps (default: 5000)
+    #[serde(rename = "portRangeEnd", default = "default_port_range_end")]
+    pub port_range_end: u16,
+
+    /// Seconds between checks for services whose process is gone (default: 30)
+    #[serde(rename = "cleanupInterval", default = "default_cleanup_interval")]
+    pub cleanup_interval: u64,
+
+    /// HTTP listen addresses, like --http-addr (default: 127.0.0.1:80)
+    #[serde(rename = "httpAddr", default)]
+    pub http_addr: Vec<String>,
+
+    /// HTTPS listen addresses, like --https-addr (default: 127.0.0.1:443)
+    #[serde(rename = "httpsAddr", default)]
+    pub https_addr: Vec<String>,
+
+    /// Serve HTTPS as well, like --https (default: false)
+    #[serde(default)]
+    pub https: bool,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            port_range_start: default_port_range_start(),
+            port_range_end: default_port_range_end(),
+            cleanup_interval: default_cleanup_interval(),
+            http_addr: Vec::new(),
+            https_addr: Vec::new(),
+            https: false,
+        }
+    }
+}
+
+fn default_port_range_start() -> u16 {
+    PORT_RANGE_START
+}
+
+fn default_port_range_end() -> u16 {
+    PORT_RANGE_END
+}
+
+fn default_cleanup_interval() -> u64 {
+    30
+}
+
+impl DaemonConfig {
+    /// Load and validate the daemon config; without a file, the defaults apply
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        let config: DaemonConfig = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid JSON in {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid settings in {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    /// Check the settings make sense together
+    pub fn validate(&self) -> Result<()> {
+        if self.port_range_start == 0 || self.port_range_start > self.port_range_end {
+            anyhow::bail!(
+                "portRangeStart ({}) must be between 1 and portRangeEnd ({})",
+                self.port_range_start,
+                self.port_range_end
+            );
+        }
+        if self.cleanup_interval == 0 {
+            anyhow::bail!("cleanupInterval must be at least 1 second");
+        }
+        if !self.https_addr.is_empty() && !self.https {
+            anyhow::bail!("httpsAddr requires \"https\": true");
+        }
+        self.http_addrs()?;
+        self.https_addrs()?;
+        Ok(())
+    }
+
+    /// Ports handed out to apps
+    pub fn port_range(&self) -> std::ops::RangeInclusive<u16> {
+        self.port_range_start..=self.port_range_end
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval)
+    }
+
+    /// Where the proxy listens, with addresses from the command line taking
+    /// precedence over the ones in the file
+    pub fn listen(&self, args: &ListenArgs) -> Result<ListenConfig> {
+        let http_addrs = if args.http_addrs.is_empty() {
+            self.http_addrs()?
+        } else {
+            args.http_addrs.clone()
+        };
+        let https_addrs = if args.https_addrs.is_empty() {
+            self.https_addrs()?
+        } else {
+            args.https_addrs.clone()
+        };
+        Ok(listen_config(args.https || self.https, http_addrs, https_addrs))
+    }
+
+    fn http_addrs(&self) -> Result<Vec<SocketAddr>> {
+        parse_addrs(&self.http_addr, parse_http_addr, "httpAddr")
+    }
+
+    fn https_addrs(&self) -> Result<Vec<SocketAddr>> {
+        parse_addrs(&self.https_addr, parse_https_addr, "httpsAddr")
+    }
+}
+
+fn parse_addrs(
+    values: &[String],
+    parse: fn(&str) -> Result<SocketAddr, String>,
+    field: &str,
+) -> Result<Vec<SocketAddr>> {
+    values
+        .iter()
+        .map(|value| parse(value).map_err(|e| anyhow::anyhow!("{}: {}", field, e)))
+        .collect()
 }
@@ -1,39 +1,126 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixListener;
-use tokio::sync::RwLock;
-use tracing::error;
+use tokio::sync::{Notify, RwLock};
+use tracing::{error, warn};
 
 use crate::log_info;
 
+use crate::config::DaemonConfig;
+use crate::health;
+use crate::logs::{self, LogStore};
+use crate::pool::BackendPool;
+use crate::ports;
+use crate::privileges;
+use crate::process::{self, Termination};
 use crate::proxy;
 use crate::tls;
 use crate::types::{
-    pid_path, registry_path, socket_path, unport_dir, Request as DaemonRequest,
-    Response as DaemonResponse, Service, PORT_RANGE_END, PORT_RANGE_START,
+    config_path, pid_path, socket_path, unport_dir, validate_domain, AppCommand, Health,
+    ListenArgs, ListenConfig, LogStream, PortPreference, Request as DaemonRequest,
+    Response as DaemonResponse, Service, PORTS_FILE, PORT_RANGE_START, REGISTRY_FILE,
 };
 
+/// Where a request for a domain and path should be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// Service that handles the request
+    pub service: Service,
+    /// Path prefix to remove before forwarding
+    pub strip_prefix: Option<String>,
+}
+
+/// How long a port from `GetPort` stays reserved for a domain that doesn't register
+const PORT_LEASE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A port handed out to a domain that hasn't registered yet
+struct Lease {
+    domain: String,
+    directory: PathBuf,
+    expires: Instant,
+}
+
+impl Lease {
+    fn is_for(&self, domain: &str, directory: &Path) -> bool {
+        self.domain == domain && self.directory == directory
+    }
+}
+
 /// Registry of services
-#[derive(Default)]
 pub struct Registry {
     services: HashMap<String, Service>,
     next_port: u16,
+    /// Ports handed out to apps
+    port_range: RangeInclusive<u16>,
+    /// Time between checks for dead processes
+    cleanup_interval: Duration,
+    /// Wakes the cleanup loop when the settings change
+    reconfigured: Arc<Notify>,
+    /// Keep-alive connections to the registered backends
+    pool: Arc<BackendPool>,
+    /// Output of the services
+    logs: Arc<LogStore>,
+    /// PIDs of background services the daemon reaps and restarts itself
+    supervised: HashSet<u32>,
+    /// When each service's current process was registered or restarted
+    started: HashMap<String, Instant>,
+    /// Port each domain had the last time it ran from a directory
+    preferred: HashMap<(String, PathBuf), u16>,
+    /// Ports handed out but not registered yet
+    leases: HashMap<u16, Lease>,
+    /// Where the registry and the remembered ports are saved (None keeps
+    /// them in memory only)
+    state_dir: Option<PathBuf>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Registry {
     pub fn new() -> Self {
+        let defaults = DaemonConfig::default();
         Self {
             services: HashMap::new(),
-            next_port: PORT_RANGE_START,
+            next_port: defaults.port_range_start,
+            port_range: defaults.port_range(),
+            cleanup_interval: defaults.cleanup_interval(),
+            reconfigured: Arc::new(Notify::new()),
+            pool: Arc::new(BackendPool::new()),
+            logs: Arc::new(LogStore::default()),
+            supervised: HashSet::new(),
+            started: HashMap::new(),
+            preferred: HashMap::new(),
+            leases: HashMap::new(),
+            state_dir: None,
+        }
+    }
+
+    /// Registry that saves its state under `dir`
+    pub fn with_state_dir(dir: PathBuf) -> Self {
+        Self {
+            state_dir: Some(dir),
+            ..Self::new()
         }
     }
 
     /// Load registry from disk
     pub fn load() -> Self {
-        let path = registry_path();
+        Self::load_from(unport_dir())
+    }
+
+    /// Load the registry saved under `dir`, and keep saving it there
+    pub fn load_from(dir: PathBuf) -> Self {
+        let mut registry = Self::with_state_dir(dir.clone());
+        let path = dir.join(REGISTRY_FILE);
         if path.exists() {
             if let Ok(content) = std::fs::read_to_string(&path) {
                 if let Ok(services) = serde_json::from_str::<HashMap<String, Service>>(&content) {
@@ -42,56 +129,227 @@ impl Registry {
                         .map(|s| s.port)
                         .max()
                         .unwrap_or(PORT_RANGE_START - 1);
-                    return Self {
-                        services,
-                        next_port: max_port + 1,
-                    };
+                    registry.services = services;
+                    registry.next_port = max_port.saturating_add(1);
                 }
             }
         }
-        Self::new()
+        if let Ok(content) = std::fs::read_to_string(dir.join(PORTS_FILE)) {
+            if let Ok(preferences) = serde_json::from_str::<Vec<PortPreference>>(&content) {
+                registry.preferred = preferences
+                    .into_iter()
+                    .map(|p| ((p.domain, p.directory), p.port))
+                    .collect();
+            }
+        }
+        registry
     }
 
     /// Save registry to disk
     pub fn save(&self) -> Result<()> {
-        let path = registry_path();
+        let Some(dir) = &self.state_dir else {
+            return Ok(());
+        };
         let content = serde_json::to_string_pretty(&self.services)?;
-        std::fs::write(&path, content)?;
+        std::fs::write(dir.join(REGISTRY_FILE), content)?;
         Ok(())
     }
 
-    /// Get next available port (checks if port is actually free)
-    pub fn get_port(&mut self) -> u16 {
+    /// Apply the daemon settings that can change while it runs
+    pub fn configure(&mut self, config: &DaemonConfig) {
+        self.port_range = config.port_range();
+        self.cleanup_interval = config.cleanup_interval();
+        self.reconfigured.notify_one();
+    }
+
+    /// Time between checks for dead processes
+    pub fn cleanup_interval(&self) -> Duration {
+        self.cleanup_interval
+    }
+
+    /// Notified each time `configure` changes the settings
+    pub fn reconfigured(&self) -> Arc<Notify> {
+        self.reconfigured.clone()
+    }
+
+    /// Save the remembered ports to disk
+    fn save_ports(&self) -> Result<()> {
+        let Some(dir) = &self.state_dir else {
+            return Ok(());
+        };
+        let mut preferences: Vec<PortPreference> = self
+            .preferred
+            .iter()
+            .map(|((domain, directory), port)| PortPreference {
+                domain: domain.clone(),
+                directory: directory.clone(),
+                port: *port,
+            })
+            .collect();
+        preferences.sort_by(|a, b| (&a.domain, &a.directory).cmp(&(&b.domain, &b.directory)));
+        std::fs::write(dir.join(PORTS_FILE), serde_json::to_string_pretty(&preferences)?)?;
+        Ok(())
+    }
+
+    /// Lease a port to a domain started from a directory
+    ///
+    /// The port stays reserved for the domain until it registers or
+    /// `PORT_LEASE_TIMEOUT` passes, so concurrent starts never get the same one.
+    pub fn assign_port(
+        &mut self,
+        domain: &str,
+        directory: &Path,
+        pin: Option<u16>,
+    ) -> Result<u16, String> {
+        let now = Instant::now();
+        self.leases.retain(|_, lease| lease.expires > now);
+
+        let port = self.pick_port(domain, directory, pin)?;
+        self.leases.retain(|_, lease| !lease.is_for(domain, directory));
+        self.leases.insert(
+            port,
+            Lease {
+                domain: domain.to_string(),
+                directory: directory.to_path_buf(),
+                expires: now + PORT_LEASE_TIMEOUT,
+            },
+        );
+        Ok(port)
+    }
+
+    /// Pick the port for a domain started from a directory
+    ///
+    /// A port pinned in unport.json is used as is, but must be free. Otherwise
+    /// the domain gets the port it had the last time it ran from this
+    /// directory, if that's free, or a new one that no other domain had.
+    fn pick_port(&mut self, domain: &str, directory: &Path, pin: Option<u16>) -> Result<u16, String> {
+        if let Some(port) = pin {
+            if port == 0 {
+                return Err("Port 0 can't be pinned in unport.json, use 1-65535".to_string());
+            }
+            if let Some(other) = self.services.values().find(|s| s.port == port) {
+                return Err(format!(
+                    "Port {} is pinned in unport.json, but {} already uses it",
+                    port, other.domain
+                ));
+            }
+            if let Some(lease) = self.leases.get(&port).filter(|l| !l.is_for(domain, directory)) {
+                return Err(format!(
+                    "Port {} is pinned in unport.json, but {} is starting on it",
+                    port, lease.domain
+                ));
+            }
+            return match check_port(port) {
+                Ok(()) => Ok(port),
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Err(format!(
+                    "Port {} is pinned in unport.json, but binding it isn't allowed ({}); ports below 1024 need root",
+                    port, e
+                )),
+                Err(_) => Err(format!(
+                    "Port {} is pinned in unport.json, but another process is listening on it",
+                    port
+                )),
+            };
+        }
+
+        // Asked again before registering, e.g. after the app failed to start
+        let leased = self.leases.iter().find(|(_, lease)| lease.is_for(domain, directory));
+        if let Some((&port, _)) = leased {
+            if is_port_available(port) {
+                return Ok(port);
+            }
+        }
+
+        let key = (domain.to_string(), directory.to_path_buf());
+        if let Some(&port) = self.preferred.get(&key) {
+            if !self.in_use(port) && is_port_available(port) {
+                return Ok(port);
+            }
+        }
+
+        // Leave the ports other domains had to them while there are others
+        let taken: HashSet<u16> = self
+            .preferred
+            .iter()
+            .filter(|(k, _)| **k != key)
+            .map(|(_, port)| *port)
+            .collect();
+        let range = self.port_range.clone();
+        let no_free_port = || format!("No free port in {}-{}", range.start(), range.end());
+        let first = self.get_port().ok_or_else(no_free_port)?;
+        let mut port = first;
+        for _ in range.clone() {
+            if !taken.contains(&port) && !self.in_use(port) {
+                return Ok(port);
+            }
+            port = self.get_port().ok_or_else(no_free_port)?;
+        }
+        Ok(first)
+    }
+
+    /// Whether a registered service uses a port, or it's leased to one about to
+    fn in_use(&self, port: u16) -> bool {
+        self.services.values().any(|s| s.port == port)
+            || self
+                .leases
+                .get(&port)
+                .is_some_and(|lease| lease.expires > Instant::now())
+    }
+
+    /// Get next available port (checks if port is actually free, and neither
+    /// registered nor leased)
+    ///
+    /// Returns `None` when every port in the range is taken.
+    pub fn get_port(&mut self) -> Option<u16> {
+        let (first, last) = (*self.port_range.start(), *self.port_range.end());
+        if !self.port_range.contains(&self.next_port) {
+            self.next_port = first;
+        }
         let start = self.next_port;
         loop {
             let port = self.next_port;
-            self.next_port += 1;
-            if self.next_port > PORT_RANGE_END {
-                self.next_port = PORT_RANGE_START;
-            }
+            self.next_port = if port >= last { first } else { port + 1 };
 
             // Check if port is actually available by trying to bind
-            if is_port_available(port) {
-                return port;
+            if !self.in_use(port) && is_port_available(port) {
+                return Some(port);
             }
 
             // Prevent infinite loop if all ports are taken
             if self.next_port == start {
-                // Fall back to returning the port anyway; it will fail at app startup
-                return port;
+                return None;
             }
         }
     }
 
     /// Register a service
-    pub fn register(&mut self, service: Service) {
-        self.services.insert(service.domain.clone(), service);
+    pub fn register(&mut self, mut service: Service) {
+        if service.options.health_check.is_some() && service.health.is_none() {
+            service.health = Some(Health::Starting);
+        }
+        self.started.insert(service.domain.clone(), Instant::now());
+        // The lease is over once the port is registered
+        self.leases
+            .retain(|port, lease| *port != service.port && !lease.is_for(&service.domain, &service.directory));
+        let key = (service.domain.clone(), service.directory.clone());
+        if self.preferred.insert(key, service.port) != Some(service.port) {
+            let _ = self.save_ports();
+        }
+        // Connections left over from whatever used this port before are stale
+        self.pool.evict(service.port);
+        if let Some(old) = self.services.insert(service.domain.clone(), service) {
+            self.pool.evict(old.port);
+        }
         let _ = self.save();
     }
 
     /// Unregister a service
     pub fn unregister(&mut self, domain: &str) -> Option<Service> {
         let service = self.services.remove(domain);
+        self.started.remove(domain);
+        if let Some(service) = &service {
+            self.pool.evict(service.port);
+        }
         let _ = self.save();
         service
     }
@@ -101,44 +359,191 @@ impl Registry {
         self.services.get(domain)
     }
 
+    /// Find the service for a host, falling back to wildcard services
+    ///
+    /// An exact match wins; otherwise the closest parent domain whose service
+    /// opted into wildcard matching is used ("a.b.myapp.localhost" tries
+    /// "b.myapp.localhost" before "myapp.localhost").
+    pub fn lookup(&self, domain: &str) -> Option<&Service> {
+        if let Some(service) = self.services.get(domain) {
+            return Some(service);
+        }
+
+        let mut parent = domain;
+        while let Some((_, rest)) = parent.split_once('.') {
+            parent = rest;
+            if let Some(service) = self.services.get(parent) {
+                if service.options.wildcard {
+                    return Some(service);
+                }
+            }
+        }
+        None
+    }
+
+    /// Resolve a request to the service that should handle it
+    ///
+    /// Path routes declared by the domain's service win over the service itself,
+    /// longest prefix first. Returns `None` for unknown domains and `Err` with the
+    /// target domain when a matching route points at a service that isn't registered.
+    pub fn resolve(&self, domain: &str, path: &str) -> Option<Result<Route, String>> {
+        let service = self.lookup(domain)?;
+
+        let rule = service
+            .options
+            .routes
+            .iter()
+            .filter(|rule| rule.matches(path))
+            .max_by_key(|rule| rule.path.len());
+
+        let route = match rule {
+            Some(rule) => match self.services.get(&rule.service) {
+                Some(target) => Ok(Route {
+                    service: target.clone(),
+                    strip_prefix: rule
+                        .strip_prefix
+                        .then(|| rule.path.trim_end_matches('/').to_string()),
+                }),
+                None => Err(rule.service.clone()),
+            },
+            None => Ok(Route {
+                service: service.clone(),
+                strip_prefix: None,
+            }),
+        };
+        Some(route)
+    }
+
     /// List all services
     pub fn list(&self) -> Vec<Service> {
         self.services.values().cloned().collect()
     }
 
+    /// Connection pool shared by the proxy for all backends
+    pub fn pool(&self) -> Arc<BackendPool> {
+        self.pool.clone()
+    }
+
+    /// Output captured from the services
+    pub fn logs(&self) -> Arc<LogStore> {
+        self.logs.clone()
+    }
+
+    /// Change a registered service in place and persist it
+    ///
+    /// Returns false if the domain isn't registered.
+    pub fn update(&mut self, domain: &str, f: impl FnOnce(&mut Service)) -> bool {
+        let Some(service) = self.services.get_mut(domain) else {
+            return false;
+        };
+        f(service);
+        let _ = self.save();
+        true
+    }
+
+    /// Route a service to another port
+    ///
+    /// The port is also remembered for the service's next start. Returns
+    /// false if the domain isn't registered.
+    pub fn set_port(&mut self, domain: &str, port: u16) -> bool {
+        let Some(service) = self.services.get_mut(domain) else {
+            return false;
+        };
+        let old = std::mem::replace(&mut service.port, port);
+        let key = (service.domain.clone(), service.directory.clone());
+        self.pool.evict(old);
+        self.pool.evict(port);
+        let _ = self.save();
+        if self.preferred.insert(key, port) != Some(port) {
+            let _ = self.save_ports();
+        }
+        true
+    }
+
+    /// Note that a service's process was just (re)started
+    pub fn mark_started(&mut self, domain: &str) {
+        self.started.insert(domain.to_string(), Instant::now());
+    }
+
+    /// Until when requests should wait for a newly started service to listen
+    ///
+    /// `None` once the service's start timeout has passed, or for services
+    /// the daemon picked up from disk.
+    pub fn start_deadline(&self, domain: &str) -> Option<Instant> {
+        let service = self.services.get(domain)?;
+        let deadline = *self.started.get(domain)?
+            + Duration::from_secs(service.options.start_timeout);
+        (deadline > Instant::now()).then_some(deadline)
+    }
+
+    /// Mark a PID as supervised by the daemon, so cleanup leaves its service
+    /// alone while it waits to be restarted
+    pub fn set_supervised(&mut self, pid: u32, supervised: bool) {
+        if supervised {
+            self.supervised.insert(pid);
+        } else {
+            self.supervised.remove(&pid);
+        }
+    }
+
     /// Clean up dead processes
     pub fn cleanup_dead(&mut self) {
         let dead: Vec<String> = self
             .services
             .iter()
-            .filter(|(_, s)| !is_process_alive(s.pid))
+            .filter(|(_, s)| !self.supervised.contains(&s.pid) && !process::is_service_alive(s))
             .map(|(domain, _)| domain.clone())
             .collect();
 
         for domain in dead {
             log_info!("Cleaning up dead service: {}", domain);
-            self.services.remove(&domain);
+            self.started.remove(&domain);
+            if let Some(service) = self.services.remove(&domain) {
+                self.pool.evict(service.port);
+            }
         }
         let _ = self.save();
     }
 }
 
-fn is_process_alive(pid: u32) -> bool {
-    unsafe { libc::kill(pid as i32, 0) == 0 }
-}
-
 /// Check if a port is available by attempting to bind to it
 fn is_port_available(port: u16) -> bool {
+    check_port(port).is_ok()
+}
+
+/// Try binding a port, returning the first error
+fn check_port(port: u16) -> std::io::Result<()> {
     // Check both IPv4 and IPv6 since apps may bind to either
-    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
-        && std::net::TcpListener::bind(("::1", port)).is_ok()
-        && std::net::TcpListener::bind(("0.0.0.0", port)).is_ok()
+    std::net::TcpListener::bind(("127.0.0.1", port))?;
+    std::net::TcpListener::bind(("::1", port))?;
+    std::net::TcpListener::bind(("0.0.0.0", port))?;
+    Ok(())
 }
 
 type SharedRegistry = Arc<RwLock<Registry>>;
 
+/// Remove dead services every `cleanup_interval`
+///
+/// A reload restarts the wait, so a shorter interval applies right away
+/// instead of after the old one runs out.
+pub async fn run_cleanup(registry: SharedRegistry) {
+    let reconfigured = registry.read().await.reconfigured();
+    loop {
+        let interval = registry.read().await.cleanup_interval();
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => registry.write().await.cleanup_dead(),
+            _ = reconfigured.notified() => {}
+        }
+    }
+}
+
 /// Run the daemon
-pub async fn run(detach: bool, https: bool) -> Result<()> {
+pub async fn run(detach: bool, args: ListenArgs) -> Result<()> {
+    // A broken config file fails startup, before anything is detached
+    let settings = DaemonConfig::load(&config_path())?;
+    let listen = settings.listen(&args)?;
+    let https = !listen.https.is_empty();
+
     // If detach requested, spawn daemon in background and exit
     if detach {
         let exe = std::env::current_exe().context("Failed to get current executable")?;
@@ -152,14 +557,25 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
         let log_file = std::fs::File::create(&log_path)
             .context("Failed to create daemon log file")?;
         let log_file_err = log_file.try_clone()?;
-
-        let mut args = vec!["daemon", "start"];
-        if https {
-            args.push("--https");
+        privileges::restore_ownership(&dir)?;
+
+        // Only pass on the flags given, so the daemon's reloads still see
+        // listen settings from the config file
+        let mut command_args = vec!["daemon".to_string(), "start".to_string()];
+        for addr in &args.http_addrs {
+            command_args.push("--http-addr".to_string());
+            command_args.push(addr.to_string());
+        }
+        if args.https {
+            command_args.push("--https".to_string());
+            for addr in &args.https_addrs {
+                command_args.push("--https-addr".to_string());
+                command_args.push(addr.to_string());
+            }
         }
 
         std::process::Command::new(exe)
-            .args(&args)
+            .args(&command_args)
             .stdin(std::process::Stdio::null())
             .stdout(log_file)
             .stderr(log_file_err)
@@ -179,13 +595,30 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     if pid_file.exists() {
         let pid_str = std::fs::read_to_string(&pid_file)?;
         if let Ok(pid) = pid_str.trim().parse::<u32>() {
-            if is_process_alive(pid) {
+            if process::is_alive(pid) {
                 anyhow::bail!("Daemon already running (PID {})", pid);
             }
         }
         std::fs::remove_file(&pid_file)?;
     }
 
+    // Bind every listener before serving anything, so a taken or privileged
+    // port fails startup instead of a background task
+    let http_listeners = proxy::bind(&listen.http).await?;
+    let https_listeners = proxy::bind(&listen.https).await?;
+    let listen = Arc::new(listen);
+
+    // Root was only needed for the listeners; continue as the sudo user so
+    // state files stay theirs and apps are signalled with their rights
+    privileges::restore_ownership(&dir)?;
+    match privileges::drop_privileges()? {
+        Some(user) => log_info!("Dropped privileges to uid {} (gid {})", user.uid, user.gid),
+        None if privileges::is_root() => {
+            warn!("Running as root without SUDO_UID; state files will be owned by root")
+        }
+        None => {}
+    }
+
     // Write PID file
     std::fs::write(&pid_file, std::process::id().to_string())?;
 
@@ -199,6 +632,7 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     let registry = Arc::new(RwLock::new(Registry::load()));
     {
         let mut reg = registry.write().await;
+        reg.configure(&settings);
         reg.cleanup_dead();
     }
 
@@ -223,8 +657,12 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     // Start Unix socket listener for CLI commands
     let socket_registry = registry.clone();
     let socket_tls = tls_acceptor.clone();
+    let socket_listen = listen.clone();
+    let socket_args = Arc::new(args.clone());
     let socket_handle = tokio::spawn(async move {
-        if let Err(e) = run_socket_server(socket_registry, socket_tls).await {
+        if let Err(e) =
+            run_socket_server(socket_registry, socket_tls, socket_listen, socket_args).await
+        {
             error!("Socket server error: {}", e);
         }
     });
@@ -232,7 +670,7 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     // Start HTTP proxy
     let proxy_registry = registry.clone();
     let proxy_handle = tokio::spawn(async move {
-        if let Err(e) = proxy::run_http(proxy_registry).await {
+        if let Err(e) = proxy::run_http(proxy_registry, http_listeners).await {
             error!("HTTP proxy server error: {}", e);
         }
     });
@@ -241,7 +679,7 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     let https_handle = if let Some(acceptor) = tls_acceptor {
         let https_registry = registry.clone();
         Some(tokio::spawn(async move {
-            if let Err(e) = proxy::run_https(https_registry, acceptor).await {
+            if let Err(e) = proxy::run_https(https_registry, acceptor, https_listeners).await {
                 error!("HTTPS proxy server error: {}", e);
             }
         }))
@@ -250,19 +688,38 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     };
 
     // Start periodic cleanup of dead processes
-    let cleanup_registry = registry.clone();
+    tokio::spawn(run_cleanup(registry.clone()));
+
+    // Re-read the config file on SIGHUP
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    let reload_registry = registry.clone();
+    let reload_listen = listen.clone();
     tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(30)).await;
-            let mut reg = cleanup_registry.write().await;
-            reg.cleanup_dead();
+        while hangup.recv().await.is_some() {
+            if let Err(e) = reload(&reload_registry, &args, &reload_listen).await {
+                error!("Failed to reload config: {}", e);
+            }
         }
     });
 
+    // Probe services that have a health check
+    tokio::spawn(health::run(registry.clone()));
+
+    // Notice apps that listen on another port than the one they were given
+    tokio::spawn(ports::run(registry.clone()));
+
+    let addrs = |addrs: &[SocketAddr]| {
+        addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+    };
     if https {
-        log_info!("Daemon running. HTTP on :80, HTTPS on :443, socket at {:?}", sock_path);
+        log_info!(
+            "Daemon running. HTTP on {}, HTTPS on {}, socket at {:?}",
+            addrs(&listen.http),
+            addrs(&listen.https),
+            sock_path
+        );
     } else {
-        log_info!("Daemon running. HTTP on :80, socket at {:?}", sock_path);
+        log_info!("Daemon running. HTTP on {}, socket at {:?}", addrs(&listen.http), sock_path);
     }
 
     // Wait for shutdown
@@ -288,10 +745,35 @@ pub async fn run(detach: bool, https: bool) -> Result<()> {
     Ok(())
 }
 
+/// Re-read the config file and apply the settings that can change while the
+/// daemon runs
+///
+/// Returns a message for the user. An invalid file leaves everything as it was.
+async fn reload(
+    registry: &SharedRegistry,
+    args: &ListenArgs,
+    listen: &ListenConfig,
+) -> Result<String, String> {
+    let path = config_path();
+    let settings = DaemonConfig::load(&path).map_err(|e| format!("{:#}", e))?;
+    let wanted = settings.listen(args).map_err(|e| format!("{:#}", e))?;
+    registry.write().await.configure(&settings);
+    log_info!("Reloaded {}", path.display());
+
+    let mut message = format!("Reloaded {}", path.display());
+    if wanted != *listen {
+        warn!("Listen address and HTTPS changes take effect when the daemon restarts");
+        message.push_str(" (listen address and HTTPS changes need a daemon restart)");
+    }
+    Ok(message)
+}
+
 /// Run the Unix socket server for CLI commands
 async fn run_socket_server(
     registry: SharedRegistry,
     tls_acceptor: Option<proxy::SharedTlsAcceptor>,
+    listen: Arc<ListenConfig>,
+    args: Arc<ListenArgs>,
 ) -> Result<()> {
     let sock = socket_path();
     let listener = UnixListener::bind(&sock)?;
@@ -305,9 +787,11 @@ async fn run_socket_server(
         let (stream, _) = listener.accept().await?;
         let registry = registry.clone();
         let tls = tls_acceptor.clone();
+        let listen = listen.clone();
+        let args = args.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_socket_client(stream, registry, tls).await {
+            if let Err(e) = handle_socket_client(stream, registry, tls, listen, args).await {
                 error!("Client error: {}", e);
             }
         });
@@ -318,55 +802,166 @@ async fn handle_socket_client(
     stream: tokio::net::UnixStream,
     registry: SharedRegistry,
     tls_acceptor: Option<proxy::SharedTlsAcceptor>,
+    listen: Arc<ListenConfig>,
+    args: Arc<ListenArgs>,
 ) -> Result<()> {
+    // Requests are authorized against the connecting user
+    let peer_uid = stream.peer_cred().context("Failed to read peer credentials")?.uid();
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
     while reader.read_line(&mut line).await? > 0 {
         let request: DaemonRequest = serde_json::from_str(&line)?;
-        let response = handle_request(request, &registry, &tls_acceptor).await;
+
+        // Subscribe before taking the snapshot, so no line falls in between
+        let follow = match &request {
+            DaemonRequest::Logs {
+                domain,
+                follow: true,
+                ..
+            } => {
+                let logs = registry.read().await.logs();
+                Some((logs.subscribe(domain), logs.lines(domain, None)))
+            }
+            _ => None,
+        };
+
+        let response =
+            handle_request(request, peer_uid, &registry, &tls_acceptor, &listen, &args).await;
         let response_json = serde_json::to_string(&response)? + "\n";
         writer.write_all(response_json.as_bytes()).await?;
         line.clear();
+
+        if let (Some((followers, snapshot)), DaemonResponse::Logs(_)) = (follow, &response) {
+            let next_seq = snapshot.map(|(_, next)| next).unwrap_or(0);
+            return follow_logs(followers, next_seq, reader, writer).await;
+        }
     }
 
     Ok(())
 }
 
+/// Send a service's new log lines until the client hangs up
+async fn follow_logs(
+    mut followers: tokio::sync::broadcast::Receiver<logs::SequencedLine>,
+    next_seq: u64,
+    mut reader: BufReader<tokio::net::unix::OwnedReadHalf>,
+    mut writer: tokio::net::unix::OwnedWriteHalf,
+) -> Result<()> {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut hangup = String::new();
+    loop {
+        let line = tokio::select! {
+            received = followers.recv() => match received {
+                Ok((seq, _)) if seq < next_seq => continue,
+                Ok((_, line)) => line,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Log follower fell behind, skipped {} lines", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            // Followers don't send anything else; EOF means they're gone
+            _ = reader.read_line(&mut hangup) => return Ok(()),
+        };
+        let response_json = serde_json::to_string(&DaemonResponse::Logs(vec![line]))? + "\n";
+        writer.write_all(response_json.as_bytes()).await?;
+    }
+}
+
 async fn handle_request(
     request: DaemonRequest,
+    peer_uid: u32,
     registry: &SharedRegistry,
     tls_acceptor: &Option<proxy::SharedTlsAcceptor>,
+    listen: &ListenConfig,
+    args: &ListenArgs,
 ) -> DaemonResponse {
+    if let Err(e) = check_domain(&request) {
+        return DaemonResponse::Error(e);
+    }
+    if let Err(e) = authorize(&request, peer_uid, &*registry.read().await, daemon_owner()) {
+        return DaemonResponse::Error(e);
+    }
+
     match request {
         DaemonRequest::Register {
             domain,
             port,
             pid,
             directory,
+            options,
         } => {
-            let mut reg = registry.write().await;
-            if reg.get(&domain).is_some() {
+            let service = Service {
+                domain,
+                port,
+                pid,
+                identity: process::identify(pid),
+                directory,
+                options,
+                owner: Some(peer_uid),
+                ..Default::default()
+            };
+            register_service(service, registry, tls_acceptor).await
+        }
+        DaemonRequest::Spawn {
+            domain,
+            port,
+            directory,
+            command,
+            options,
+        } => {
+            if registry.read().await.get(&domain).is_some() {
                 return DaemonResponse::Error(format!("Domain '{}' already registered", domain));
             }
-            reg.register(Service {
+            let logs = registry.read().await.logs();
+            let mut child = match spawn_service(&command, &directory, &domain, &logs) {
+                Ok(child) => child,
+                Err(e) => {
+                    return DaemonResponse::Error(format!("Failed to start {}: {:#}", domain, e))
+                }
+            };
+            let pid = child.id().unwrap_or_default();
+
+            let service = Service {
                 domain: domain.clone(),
                 port,
                 pid,
+                identity: process::identify(pid),
                 directory,
-            });
-            log_info!("Registered: {} -> localhost:{}", domain, port);
-
-            // If HTTPS is enabled, regenerate certificate with new domain
-            if let Some(acceptor) = tls_acceptor {
-                let domains: Vec<String> = reg.list().into_iter().map(|s| s.domain).collect();
-                if let Err(e) = regenerate_tls_cert(&domains, acceptor).await {
-                    error!("Failed to regenerate TLS cert: {}", e);
+                options,
+                owner: Some(peer_uid),
+                ..Default::default()
+            };
+            match register_service(service, registry, tls_acceptor).await {
+                DaemonResponse::Ok(_) => {
+                    registry.write().await.set_supervised(pid, true);
+                    watch_service(child, domain.clone(), command, logs, registry.clone());
+                    DaemonResponse::Ok(Some(format!("Started {} (PID {})", domain, pid)))
+                }
+                response => {
+                    let _ = child.start_kill();
+                    response
                 }
             }
-
-            DaemonResponse::Ok(Some(format!("Registered {}", domain)))
+        }
+        DaemonRequest::Logs { domain, since, .. } => {
+            let reg = registry.read().await;
+            match reg.logs().lines(&domain, since) {
+                Some((lines, _)) => DaemonResponse::Logs(lines),
+                // Running, but hasn't printed anything yet
+                None if reg.get(&domain).is_some() => DaemonResponse::Logs(Vec::new()),
+                None => DaemonResponse::Error(format!("No logs for '{}'", domain)),
+            }
+        }
+        DaemonRequest::AppendLogs { domain, lines } => {
+            let logs = registry.read().await.logs();
+            for line in lines {
+                logs.append(&domain, line);
+            }
+            DaemonResponse::Ok(None)
         }
         DaemonRequest::Unregister { domain } => {
             let mut reg = registry.write().await;
@@ -377,24 +972,28 @@ async fn handle_request(
                 DaemonResponse::Error(format!("Domain '{}' not found", domain))
             }
         }
-        DaemonRequest::GetPort => {
+        DaemonRequest::GetPort {
+            domain,
+            directory,
+            port,
+        } => {
             let mut reg = registry.write().await;
-            let port = reg.get_port();
-            DaemonResponse::Port(port)
+            match reg.assign_port(&domain, &directory, port) {
+                Ok(port) => DaemonResponse::Port(port),
+                Err(e) => DaemonResponse::Error(e),
+            }
         }
         DaemonRequest::List => {
             let reg = registry.read().await;
             DaemonResponse::Services(reg.list())
         }
         DaemonRequest::Stop { domain } => {
-            let mut reg = registry.write().await;
-            if let Some(service) = reg.unregister(&domain) {
-                // Send SIGTERM to the process
-                unsafe {
-                    libc::kill(service.pid as i32, libc::SIGTERM);
-                }
+            // Unregister first, so a supervised service isn't restarted
+            let service = registry.write().await.unregister(&domain);
+            if let Some(service) = service {
+                let message = stop_service(&service).await;
                 log_info!("Stopped: {}", domain);
-                DaemonResponse::Ok(Some(format!("Stopped {}", domain)))
+                DaemonResponse::Ok(Some(message))
             } else {
                 DaemonResponse::Error(format!("Domain '{}' not found", domain))
             }
@@ -406,9 +1005,320 @@ async fn handle_request(
         DaemonRequest::HttpsStatus => {
             DaemonResponse::HttpsEnabled(tls_acceptor.is_some())
         }
+        DaemonRequest::Listeners => DaemonResponse::Listeners(listen.clone()),
+        DaemonRequest::Reload => match reload(registry, args, listen).await {
+            Ok(message) => DaemonResponse::Ok(Some(message)),
+            Err(e) => DaemonResponse::Error(e),
+        },
     }
 }
 
+/// How long to wait for a stopped service's port to be free again
+const PORT_RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Stop an unregistered service's processes and check that its port is free
+///
+/// Returns a message for the user.
+pub async fn stop_service(service: &Service) -> String {
+    let timeout = Duration::from_secs(service.options.stop_timeout);
+    let mut message = match process::terminate(service.pid, service.identity.as_ref(), timeout).await {
+        Termination::Killed => {
+            warn!(
+                "{} didn't exit within {}s of SIGTERM, killed it",
+                service.domain, service.options.stop_timeout
+            );
+            format!("Stopped {} (killed after {}s)", service.domain, service.options.stop_timeout)
+        }
+        Termination::Exited | Termination::NotRunning => format!("Stopped {}", service.domain),
+    };
+
+    if !wait_for_port_release(service.port, PORT_RELEASE_TIMEOUT).await {
+        warn!("Port {} is still in use after stopping {}", service.port, service.domain);
+        message.push_str(&format!(", but port {} is still in use", service.port));
+    }
+    message
+}
+
+async fn wait_for_port_release(port: u16, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if is_port_available(port) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Add a service to the registry, refreshing the TLS certificate if needed
+async fn register_service(
+    service: Service,
+    registry: &SharedRegistry,
+    tls_acceptor: &Option<proxy::SharedTlsAcceptor>,
+) -> DaemonResponse {
+    let domain = service.domain.clone();
+    let port = service.port;
+
+    let mut reg = registry.write().await;
+    if reg.get(&domain).is_some() {
+        return DaemonResponse::Error(format!("Domain '{}' already registered", domain));
+    }
+    reg.register(service);
+    log_info!("Registered: {} -> localhost:{}", domain, port);
+
+    // If HTTPS is enabled, regenerate certificate with new domain
+    if let Some(acceptor) = tls_acceptor {
+        let domains: Vec<String> = reg.list().iter().flat_map(|s| s.cert_names()).collect();
+        if let Err(e) = regenerate_tls_cert(&domains, acceptor).await {
+            error!("Failed to regenerate TLS cert: {}", e);
+        }
+    }
+
+    DaemonResponse::Ok(Some(format!("Registered {}", domain)))
+}
+
+/// Start a background service's process
+///
+/// The process gets its own process group, so it isn't tied to the terminal
+/// the daemon (or the CLI that asked for it) runs in. Output is appended to
+/// `log_path`.
+fn spawn_service(
+    command: &AppCommand,
+    directory: &std::path::Path,
+    domain: &str,
+    logs: &Arc<LogStore>,
+) -> Result<tokio::process::Child> {
+    let mut child = tokio::process::Command::new(&command.program)
+        .args(&command.args)
+        .env_clear()
+        .envs(&command.env)
+        .current_dir(directory)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'", command.program))?;
+
+    if let Some(stdout) = child.stdout.take() {
+        logs::capture(logs.clone(), domain.to_string(), LogStream::Stdout, stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        logs::capture(logs.clone(), domain.to_string(), LogStream::Stderr, stderr);
+    }
+    Ok(child)
+}
+
+/// First delay between restarts of a crashed service
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// Longest delay between restarts; a process that ran longer than this
+/// starts over at the base delay
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Delay before the given restart attempt (0-based), doubling each time
+pub fn restart_backoff(attempt: u32) -> Duration {
+    RESTART_BACKOFF_BASE
+        .saturating_mul(1u32 << attempt.min(16))
+        .min(RESTART_BACKOFF_MAX)
+}
+
+/// Supervise a background service
+///
+/// Reaps the process when it exits, records how it exited, and starts it
+/// again according to the service's restart policy. A service that exits
+/// cleanly is dropped from the registry right away; a crashed one is left for
+/// `cleanup_dead`, so its exit status still shows up in the meantime.
+fn watch_service(
+    mut child: tokio::process::Child,
+    domain: String,
+    command: AppCommand,
+    logs: Arc<LogStore>,
+    registry: SharedRegistry,
+) {
+    tokio::spawn(async move {
+        let mut pid = child.id().unwrap_or_default();
+        let mut attempt = 0;
+
+        loop {
+            let started = Instant::now();
+            let (success, exit) = match child.wait().await {
+                Ok(status) => (status.success(), status.to_string()),
+                Err(e) => (false, format!("unknown ({})", e)),
+            };
+            log_info!("{} (PID {}) exited: {}", domain, pid, exit);
+
+            // Record the exit, unless the service was stopped or replaced meanwhile
+            let mut options = None;
+            registry.write().await.update(&domain, |s| {
+                if s.pid == pid {
+                    s.last_exit = Some(exit.clone());
+                    if !success {
+                        s.crashes += 1;
+                    }
+                    options = Some(s.options.clone());
+                }
+            });
+            let Some(options) = options else {
+                break;
+            };
+
+            if started.elapsed() >= RESTART_BACKOFF_MAX {
+                attempt = 0;
+            }
+            if !options.restart.should_restart(success) || attempt >= options.max_retries {
+                if options.restart.should_restart(success) {
+                    warn!("{} keeps exiting, giving up after {} restarts", domain, attempt);
+                }
+                if success {
+                    registry.write().await.unregister(&domain);
+                    log_info!("Unregistered: {}", domain);
+                }
+                break;
+            }
+
+            let delay = restart_backoff(attempt);
+            attempt += 1;
+            log_info!(
+                "Restarting {} in {:?} (attempt {}/{})",
+                domain,
+                delay,
+                attempt,
+                options.max_retries
+            );
+            tokio::time::sleep(delay).await;
+
+            // Start it again, unless it was stopped during the backoff
+            let mut reg = registry.write().await;
+            let Some(service) = reg.get(&domain).filter(|s| s.pid == pid).cloned() else {
+                break;
+            };
+            match spawn_service(&command, &service.directory, &domain, &logs) {
+                Ok(new_child) => {
+                    reg.set_supervised(pid, false);
+                    pid = new_child.id().unwrap_or_default();
+                    child = new_child;
+                    reg.set_supervised(pid, true);
+                    reg.update(&domain, |s| {
+                        s.pid = pid;
+                        s.identity = process::identify(pid);
+                        if s.options.health_check.is_some() {
+                            s.health = Some(Health::Starting);
+                        }
+                    });
+                    reg.mark_started(&domain);
+                    reg.pool().evict(service.port);
+                    log_info!("Restarted {} (PID {})", domain, pid);
+                }
+                Err(e) => {
+                    error!("Failed to restart {}: {:#}", domain, e);
+                    reg.unregister(&domain);
+                    break;
+                }
+            }
+        }
+
+        registry.write().await.set_supervised(pid, false);
+    });
+}
+
+/// User the daemon acts for: the sudo user, or whoever runs it
+fn daemon_owner() -> u32 {
+    privileges::invoking_user()
+        .map(|user| user.uid)
+        .unwrap_or_else(|| unsafe { libc::geteuid() })
+}
+
+/// Check the domain of a request that introduces one
+///
+/// Applies to root too: a domain names the service's log file.
+fn check_domain(request: &DaemonRequest) -> Result<(), String> {
+    match request {
+        DaemonRequest::Register { domain, .. }
+        | DaemonRequest::Spawn { domain, .. }
+        | DaemonRequest::GetPort { domain, .. } => validate_domain(domain),
+        _ => Ok(()),
+    }
+}
+
+/// Check that a user may send a request
+///
+/// Root may do anything. Other users may only touch their own services, only
+/// register processes they own, and only shut down a daemon running for them.
+fn authorize(
+    request: &DaemonRequest,
+    peer_uid: u32,
+    registry: &Registry,
+    daemon_uid: u32,
+) -> Result<(), String> {
+    if peer_uid == 0 {
+        return Ok(());
+    }
+
+    let check_owner = |domain: &str| match registry.get(domain).and_then(|s| s.owner) {
+        Some(owner) if owner != peer_uid => Err(format!(
+            "Permission denied: '{}' belongs to uid {}, not uid {}",
+            domain, owner, peer_uid
+        )),
+        _ => Ok(()),
+    };
+
+    match request {
+        DaemonRequest::Unregister { domain }
+        | DaemonRequest::Stop { domain }
+        | DaemonRequest::Logs { domain, .. } => check_owner(domain),
+        DaemonRequest::AppendLogs { domain, .. } => match registry.get(domain) {
+            Some(_) => check_owner(domain),
+            None => Err(format!("Domain '{}' not found", domain)),
+        },
+        DaemonRequest::Spawn { domain, .. } => {
+            // Processes are started with the daemon's rights, not the caller's
+            if peer_uid != daemon_uid {
+                return Err(format!(
+                    "Permission denied: the daemon runs for uid {}, not uid {}",
+                    daemon_uid, peer_uid
+                ));
+            }
+            match registry.lookup(domain) {
+                Some(parent) => check_owner(&parent.domain),
+                None => Ok(()),
+            }
+        }
+        DaemonRequest::Register { domain, pid, .. } => {
+            // A subdomain of someone else's wildcard service would take over their traffic
+            if let Some(parent) = registry.lookup(domain) {
+                check_owner(&parent.domain)?;
+            }
+            match process_owner(*pid) {
+                Some(owner) if owner != peer_uid => Err(format!(
+                    "Permission denied: process {} belongs to uid {}, not uid {}",
+                    pid, owner, peer_uid
+                )),
+                _ => Ok(()),
+            }
+        }
+        DaemonRequest::Shutdown | DaemonRequest::Reload if peer_uid != daemon_uid => Err(format!(
+            "Permission denied: the daemon runs for uid {}, not uid {}",
+            daemon_uid, peer_uid
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// UID owning a process, where the platform makes it easy to find out
+#[cfg(target_os = "linux")]
+fn process_owner(pid: u32) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(format!("/proc/{}", pid)).ok().map(|m| m.uid())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_owner(_pid: u32) -> Option<u32> {
+    None
+}
+
 /// Regenerate TLS certificate with new domains and hot-reload it
 async fn regenerate_tls_cert(
     domains: &[String],
@@ -448,6 +1358,7 @@ mod tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/test"),
+            ..Default::default()
         };
         registry.register(service);
 
@@ -464,6 +1375,7 @@ mod tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/test"),
+            ..Default::default()
         };
         registry.register(service);
 
@@ -480,12 +1392,14 @@ mod tests {
             port: 4000,
             pid: 1000,
             directory: PathBuf::from("/a"),
+            ..Default::default()
         });
         registry.register(Service {
             domain: "b.localhost".to_string(),
             port: 4001,
             pid: 1001,
             directory: PathBuf::from("/b"),
+            ..Default::default()
         });
 
         let list = registry.list();
@@ -495,8 +1409,8 @@ mod tests {
     #[test]
     fn test_registry_get_port_increments() {
         let mut registry = Registry::new();
-        let port1 = registry.get_port();
-        let port2 = registry.get_port();
+        let port1 = registry.get_port().unwrap();
+        let port2 = registry.get_port().unwrap();
         assert_ne!(port1, port2);
         assert!(port1 >= PORT_RANGE_START);
         assert!(port2 >= PORT_RANGE_START);
@@ -510,6 +1424,7 @@ mod tests {
             port: 4000,
             pid: 4000000, // Non-existent PID
             directory: PathBuf::from("/dead"),
+            ..Default::default()
         });
 
         registry.cleanup_dead();
@@ -524,6 +1439,7 @@ mod tests {
             port: 4000,
             pid: std::process::id(),
             directory: PathBuf::from("/alive"),
+            ..Default::default()
         });
 
         registry.cleanup_dead();
@@ -531,21 +1447,52 @@ mod tests {
     }
 
     #[test]
-    fn test_is_process_alive_current() {
-        assert!(is_process_alive(std::process::id()));
-    }
+    fn test_registry_configure_port_range() {
+        let mut registry = Registry::new();
+        registry.configure(&DaemonConfig {
+            port_range_start: 6000,
+            port_range_end: 6002,
+            cleanup_interval: 5,
+            ..Default::default()
+        });
+        assert_eq!(registry.cleanup_interval(), Duration::from_secs(5));
 
-    #[test]
-    fn test_is_process_alive_dead() {
-        assert!(!is_process_alive(4000000));
+        let ports: Vec<u16> = (0..6).map(|_| registry.get_port().unwrap()).collect();
+        assert!(ports.iter().all(|port| (6000..=6002).contains(port)), "{:?}", ports);
     }
 
-    #[test]
-    fn test_is_process_alive_parent() {
-        // Parent process should always exist
-        // On Unix, we can use getppid()
-        let ppid = unsafe { libc::getppid() } as u32;
-        assert!(is_process_alive(ppid));
+    #[tokio::test]
+    async fn test_cleanup_uses_shortened_interval_right_away() {
+        let mut registry = Registry::new();
+        registry.configure(&DaemonConfig {
+            cleanup_interval: 3600,
+            ..Default::default()
+        });
+        let registry = Arc::new(RwLock::new(registry));
+        let cleanup = tokio::spawn(run_cleanup(registry.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        {
+            let mut reg = registry.write().await;
+            reg.register(Service {
+                domain: "dead.localhost".to_string(),
+                port: 4000,
+                pid: 4000000, // Non-existent PID
+                directory: PathBuf::from("/dead"),
+                ..Default::default()
+            });
+            reg.configure(&DaemonConfig {
+                cleanup_interval: 1,
+                ..Default::default()
+            });
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !registry.read().await.list().is_empty() {
+            assert!(Instant::now() < deadline, "Dead service still registered");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        cleanup.abort();
     }
 
     #[test]
@@ -567,4 +1514,328 @@ mod tests {
         // Port should not be available (we're holding it)
         assert!(!is_port_available(port));
     }
+
+    fn owned_registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Service {
+            domain: "alice.localhost".to_string(),
+            port: 4000,
+            pid: 1,
+            directory: PathBuf::from("/home/alice/app"),
+            owner: Some(1000),
+            options: crate::types::ServiceOptions {
+                wildcard: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        registry.register(Service {
+            domain: "legacy.localhost".to_string(),
+            port: 4001,
+            pid: 2,
+            directory: PathBuf::from("/app"),
+            ..Default::default()
+        });
+        registry
+    }
+
+    fn stop(domain: &str) -> DaemonRequest {
+        DaemonRequest::Stop {
+            domain: domain.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_authorize_owner_may_stop() {
+        let registry = owned_registry();
+        assert!(authorize(&stop("alice.localhost"), 1000, &registry, 1000).is_ok());
+        let unregister = DaemonRequest::Unregister {
+            domain: "alice.localhost".to_string(),
+        };
+        assert!(authorize(&unregister, 1000, &registry, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_refuses_other_user() {
+        let registry = owned_registry();
+        let err = authorize(&stop("alice.localhost"), 1001, &registry, 1000).unwrap_err();
+        assert!(err.contains("Permission denied"));
+        assert!(err.contains("uid 1000"));
+
+        let unregister = DaemonRequest::Unregister {
+            domain: "alice.localhost".to_string(),
+        };
+        assert!(authorize(&unregister, 1001, &registry, 1000).is_err());
+    }
+
+    #[test]
+    fn test_authorize_root_may_do_anything() {
+        let registry = owned_registry();
+        assert!(authorize(&stop("alice.localhost"), 0, &registry, 1000).is_ok());
+        assert!(authorize(&DaemonRequest::Shutdown, 0, &registry, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_services_without_owner() {
+        let registry = owned_registry();
+        assert!(authorize(&stop("legacy.localhost"), 1001, &registry, 1000).is_ok());
+        assert!(authorize(&stop("missing.localhost"), 1001, &registry, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_shutdown_only_for_daemon_user() {
+        let registry = owned_registry();
+        assert!(authorize(&DaemonRequest::Shutdown, 1000, &registry, 1000).is_ok());
+        assert!(authorize(&DaemonRequest::Shutdown, 1001, &registry, 1000).is_err());
+        assert!(authorize(&DaemonRequest::Reload, 1000, &registry, 1000).is_ok());
+        assert!(authorize(&DaemonRequest::Reload, 1001, &registry, 1000).is_err());
+    }
+
+    #[test]
+    fn test_authorize_read_only_requests() {
+        let registry = owned_registry();
+        assert!(authorize(&DaemonRequest::List, 1001, &registry, 1000).is_ok());
+        let get_port = DaemonRequest::GetPort {
+            domain: "api.localhost".to_string(),
+            directory: PathBuf::from("/app"),
+            port: None,
+        };
+        assert!(authorize(&get_port, 1001, &registry, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_logs_only_for_owner() {
+        let registry = owned_registry();
+        let logs = DaemonRequest::Logs {
+            domain: "alice.localhost".to_string(),
+            follow: true,
+            since: None,
+        };
+        assert!(authorize(&logs, 1000, &registry, 1000).is_ok());
+        assert!(authorize(&logs, 1001, &registry, 1000).is_err());
+
+        let append = |domain: &str| DaemonRequest::AppendLogs {
+            domain: domain.to_string(),
+            lines: Vec::new(),
+        };
+        assert!(authorize(&append("alice.localhost"), 1000, &registry, 1000).is_ok());
+        assert!(authorize(&append("alice.localhost"), 1001, &registry, 1000).is_err());
+        assert!(authorize(&append("nobody.localhost"), 1000, &registry, 1000).is_err());
+    }
+
+    #[test]
+    fn test_authorize_register_under_foreign_wildcard() {
+        let registry = owned_registry();
+        let register = |domain: &str| DaemonRequest::Register {
+            domain: domain.to_string(),
+            port: 4100,
+            pid: std::process::id(),
+            directory: PathBuf::from("/tmp"),
+            options: Default::default(),
+        };
+        let me = unsafe { libc::getuid() };
+        assert!(authorize(&register("tenant.alice.localhost"), 1001, &registry, 1000).is_err());
+        // A user registering their own process under a fresh domain is fine
+        if me != 0 {
+            assert!(authorize(&register("bob.localhost"), me, &registry, 1000).is_ok());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_authorize_register_foreign_process() {
+        let registry = Registry::new();
+        // PID 1 belongs to root
+        let register = DaemonRequest::Register {
+            domain: "sneaky.localhost".to_string(),
+            port: 4100,
+            pid: 1,
+            directory: PathBuf::from("/tmp"),
+            options: Default::default(),
+        };
+        let err = authorize(&register, 1001, &registry, 1000).unwrap_err();
+        assert!(err.contains("process 1"));
+    }
+
+    #[test]
+    fn test_authorize_spawn_only_for_daemon_user() {
+        let registry = Registry::new();
+        let spawn = DaemonRequest::Spawn {
+            domain: "bg.localhost".to_string(),
+            port: 4100,
+            directory: PathBuf::from("/tmp"),
+            command: AppCommand::default(),
+            options: Default::default(),
+        };
+        assert!(authorize(&spawn, 1000, &registry, 1000).is_ok());
+        assert!(authorize(&spawn, 0, &registry, 1000).is_ok());
+        let err = authorize(&spawn, 1001, &registry, 1000).unwrap_err();
+        assert!(err.contains("Permission denied"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_request_rejects_path_like_domains() {
+        let registry: SharedRegistry = Arc::new(RwLock::new(Registry::new()));
+        for domain in ["../../home/victim/.bashrc-x", "/etc/passwd", "", "a..localhost"] {
+            let register = DaemonRequest::Register {
+                domain: domain.to_string(),
+                port: 4100,
+                pid: std::process::id(),
+                directory: PathBuf::from("/tmp"),
+                options: Default::default(),
+            };
+            // Not even root gets past it
+            let response = handle_request(
+                register,
+                0,
+                &registry,
+                &None,
+                &ListenConfig::default(),
+                &ListenArgs::default(),
+            )
+            .await;
+            assert!(
+                matches!(&response, DaemonResponse::Error(e) if e.contains("Invalid domain")),
+                "{:?}: {:?}",
+                domain,
+                response
+            );
+        }
+        assert!(registry.read().await.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spawned_service_is_reaped_and_unregistered() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(LogStore::new(dir.path().join("logs")));
+        let mut command = AppCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo \"hello from $GREETING\"".to_string()],
+            ..Default::default()
+        };
+        command.env.insert("GREETING".to_string(), "unport".to_string());
+
+        let child = spawn_service(&command, dir.path(), "bg.localhost", &logs).unwrap();
+        let pid = child.id().unwrap();
+
+        let registry: SharedRegistry = Arc::new(RwLock::new(Registry::new()));
+        registry.write().await.register(Service {
+            domain: "bg.localhost".to_string(),
+            port: 4100,
+            pid,
+            directory: dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        registry.write().await.set_supervised(pid, true);
+        watch_service(child, "bg.localhost".to_string(), command, logs.clone(), registry.clone());
+
+        // The watcher unregisters the service once the process exits
+        for _ in 0..100 {
+            if registry.read().await.get("bg.localhost").is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(registry.read().await.get("bg.localhost").is_none());
+
+        // Output went to the ring buffer and the log file
+        let (lines, _) = logs.lines("bg.localhost", None).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].stream, LogStream::Stdout);
+        assert_eq!(lines[0].text, "hello from unport");
+        let log = std::fs::read_to_string(logs.path("bg.localhost").unwrap()).unwrap();
+        assert_eq!(log, "hello from unport\n");
+    }
+
+    #[test]
+    fn test_spawn_service_missing_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = AppCommand {
+            program: "definitely-not-a-real-program-xyz".to_string(),
+            ..Default::default()
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let logs = Arc::new(LogStore::new(dir.path().to_path_buf()));
+        let err = spawn_service(&command, dir.path(), "x.localhost", &logs).unwrap_err();
+        assert!(format!("{:#}", err).contains("definitely-not-a-real-program-xyz"));
+    }
+
+    #[test]
+    fn test_restart_backoff_doubles_up_to_max() {
+        assert_eq!(restart_backoff(0), Duration::from_secs(1));
+        assert_eq!(restart_backoff(1), Duration::from_secs(2));
+        assert_eq!(restart_backoff(4), Duration::from_secs(16));
+        assert_eq!(restart_backoff(6), RESTART_BACKOFF_MAX);
+        assert_eq!(restart_backoff(u32::MAX), RESTART_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_cleanup_keeps_supervised_services() {
+        let mut registry = Registry::new();
+        registry.register(Service {
+            domain: "restarting.localhost".to_string(),
+            port: 4000,
+            pid: 4000000, // not running
+            directory: PathBuf::from("/test"),
+            ..Default::default()
+        });
+        registry.set_supervised(4000000, true);
+        registry.cleanup_dead();
+        assert!(registry.get("restarting.localhost").is_some());
+
+        registry.set_supervised(4000000, false);
+        registry.cleanup_dead();
+        assert!(registry.get("restarting.localhost").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_crashing_service_is_restarted_then_given_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = Arc::new(LogStore::new(dir.path().to_path_buf()));
+        let command = AppCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo started; exit 3".to_string()],
+            ..Default::default()
+        };
+
+        let child = spawn_service(&command, dir.path(), "crash.localhost", &logs).unwrap();
+        let pid = child.id().unwrap();
+        let registry: SharedRegistry = Arc::new(RwLock::new(Registry::new()));
+        registry.write().await.register(Service {
+            domain: "crash.localhost".to_string(),
+            port: 4100,
+            pid,
+            directory: dir.path().to_path_buf(),
+            options: crate::types::ServiceOptions {
+                restart: crate::types::RestartPolicy::OnFailure,
+                max_retries: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        registry.write().await.set_supervised(pid, true);
+        watch_service(child, "crash.localhost".to_string(), command, logs.clone(), registry.clone());
+
+        // One restart after a 1s backoff, then the watcher gives up
+        for _ in 0..100 {
+            let reg = registry.read().await;
+            if reg.get("crash.localhost").unwrap().crashes == 2 && reg.supervised.is_empty() {
+                break;
+            }
+            drop(reg);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let reg = registry.read().await;
+        let service = reg.get("crash.localhost").unwrap();
+        assert_eq!(service.crashes, 2);
+        assert_eq!(service.last_exit.as_deref(), Some("exit status: 3"));
+        assert_ne!(service.pid, pid, "service should have been restarted");
+        assert!(reg.supervised.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(logs.path("crash.localhost").unwrap()).unwrap(),
+            "started\nstarted\n"
+        );
+    }
 }
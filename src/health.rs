@@ -0,0 +1,274 @@
+//! HTTP health checks for registered services
+//!
+//! Services that declare a `healthCheck` in unport.json are probed on their
+//! own interval. A new process is "starting" until its first successful probe
+//! (or until its start period runs out), then "healthy" or "unhealthy"
+//! depending on the latest probe.
+
+use anyhow::{Context, Result};
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::client::conn::http1;
+use hyper::{header, Request};
+use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::warn;
+
+use crate::log_info;
+
+use crate::proxy::SharedRegistry;
+use crate::types::{Health, HealthCheck};
+
+/// How often the scheduler looks for services that are due for a probe
+const TICK: Duration = Duration::from_secs(1);
+
+/// Probe bookkeeping for one process of a service
+struct ProbeState {
+    pid: u32,
+    /// When this process was first seen
+    started: Instant,
+    last_probe: Option<Instant>,
+}
+
+/// Probe every service with a health check, forever
+pub async fn run(registry: SharedRegistry) {
+    let mut states: HashMap<String, ProbeState> = HashMap::new();
+
+    loop {
+        let due: Vec<(String, u32, u16, HealthCheck, Duration)> = {
+            let reg = registry.read().await;
+            let services = reg.list();
+            states.retain(|domain, state| {
+                services
+                    .iter()
+                    .any(|s| &s.domain == domain && s.pid == state.pid)
+            });
+
+            let now = Instant::now();
+            services
+                .into_iter()
+                .filter_map(|s| {
+                    let check = s.options.health_check.clone()?;
+                    let state = states.entry(s.domain.clone()).or_insert(ProbeState {
+                        pid: s.pid,
+                        started: now,
+                        last_probe: None,
+                    });
+                    let interval = Duration::from_secs(check.interval.max(1));
+                    if state.last_probe.is_some_and(|last| now - last < interval) {
+                        return None;
+                    }
+                    state.last_probe = Some(now);
+                    Some((s.domain, s.pid, s.port, check, now - state.started))
+                })
+                .collect()
+        };
+
+        for (domain, pid, port, check, age) in due {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let result = probe(port, &check).await;
+                let healthy = matches!(result, Ok(status) if status == check.expected_status);
+
+                let mut reg = registry.write().await;
+                let Some(current) = reg.get(&domain).filter(|s| s.pid == pid).map(|s| s.health)
+                else {
+                    return;
+                };
+                let start_period = Duration::from_secs(check.start_period);
+                let next = next_health(current, healthy, age, start_period);
+                if Some(next) == current {
+                    return;
+                }
+
+                match (&result, next) {
+                    (_, Health::Healthy) => log_info!("{} is healthy", domain),
+                    (Ok(status), _) => {
+                        warn!("{} is {}: {} returned {}", domain, next.as_str(), check.path, status)
+                    }
+                    (Err(e), _) => warn!("{} is {}: {:#}", domain, next.as_str(), e),
+                }
+                reg.update(&domain, |s| s.health = Some(next));
+            });
+        }
+
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+/// Work out a service's health after a probe
+///
+/// `age` is how long the current process has been around.
+pub fn next_health(
+    current: Option<Health>,
+    healthy: bool,
+    age: Duration,
+    start_period: Duration,
+) -> Health {
+    if healthy {
+        return Health::Healthy;
+    }
+    match current {
+        // Give a new process time to compile and boot
+        None | Some(Health::Starting) if age < start_period => Health::Starting,
+        _ => Health::Unhealthy,
+    }
+}
+
+/// Send one health-check request and return the status code
+pub async fn probe(port: u16, check: &HealthCheck) -> Result<u16> {
+    let timeout = Duration::from_secs(check.timeout.max(1));
+    tokio::time::timeout(timeout, probe_inner(port, &check.path))
+        .await
+        .with_context(|| format!("no response within {}s", timeout.as_secs()))?
+}
+
+async fn probe_inner(port: u16, path: &str) -> Result<u16> {
+    // Apps may listen on either loopback address
+    let stream = match TcpStream::connect(("127.0.0.1", port)).await {
+        Ok(stream) => stream,
+        Err(_) => TcpStream::connect(("::1", port))
+            .await
+            .with_context(|| format!("nothing listening on port {}", port))?,
+    };
+
+    let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(conn);
+
+    let req = Request::get(path)
+        .header(header::HOST, format!("localhost:{}", port))
+        .header(header::USER_AGENT, "unport-health-check")
+        .body(Empty::<Bytes>::new())?;
+    let response = sender.send_request(req).await?;
+    Ok(response.status().as_u16())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const START: Duration = Duration::from_secs(60);
+
+    fn check(path: &str) -> HealthCheck {
+        HealthCheck {
+            path: path.to_string(),
+            interval: 1,
+            timeout: 1,
+            expected_status: 200,
+            start_period: 60,
+        }
+    }
+
+    /// Backend answering every request with the given status line
+    async fn backend(status: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_success_is_healthy() {
+        let secs = Duration::from_secs;
+        assert_eq!(next_health(None, true, secs(0), START), Health::Healthy);
+        assert_eq!(next_health(Some(Health::Starting), true, secs(5), START), Health::Healthy);
+        assert_eq!(next_health(Some(Health::Unhealthy), true, secs(500), START), Health::Healthy);
+    }
+
+    #[test]
+    fn test_failure_while_starting() {
+        let secs = Duration::from_secs;
+        assert_eq!(next_health(None, false, secs(0), START), Health::Starting);
+        assert_eq!(next_health(Some(Health::Starting), false, secs(30), START), Health::Starting);
+        assert_eq!(next_health(Some(Health::Starting), false, secs(60), START), Health::Unhealthy);
+    }
+
+    #[test]
+    fn test_failure_after_healthy() {
+        let secs = Duration::from_secs;
+        assert_eq!(next_health(Some(Health::Healthy), false, secs(1), START), Health::Unhealthy);
+    }
+
+    #[tokio::test]
+    async fn test_probe_returns_status() {
+        let port = backend("200 OK").await;
+        assert_eq!(probe(port, &check("/health")).await.unwrap(), 200);
+
+        let port = backend("503 Service Unavailable").await;
+        assert_eq!(probe(port, &check("/health")).await.unwrap(), 503);
+    }
+
+    #[tokio::test]
+    async fn test_probe_nothing_listening() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        assert!(probe(port, &check("/health")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_times_out() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            loop {
+                held.push(listener.accept().await.unwrap());
+            }
+        });
+
+        let err = probe(port, &check("/health")).await.unwrap_err();
+        assert!(err.to_string().contains("no response"));
+    }
+
+    #[tokio::test]
+    async fn test_run_marks_service_healthy() {
+        use crate::daemon::Registry;
+        use crate::types::{Service, ServiceOptions};
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+
+        let port = backend("200 OK").await;
+        let mut registry = Registry::new();
+        registry.register(Service {
+            domain: "checked.localhost".to_string(),
+            port,
+            pid: std::process::id(),
+            options: ServiceOptions {
+                health_check: Some(check("/health")),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert_eq!(registry.get("checked.localhost").unwrap().health, Some(Health::Starting));
+
+        let registry = Arc::new(RwLock::new(registry));
+        tokio::spawn(run(registry.clone()));
+        for _ in 0..50 {
+            if registry.read().await.get("checked.localhost").unwrap().health
+                == Some(Health::Healthy)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let health = registry.read().await.get("checked.localhost").unwrap().health;
+        assert_eq!(health, Some(Health::Healthy));
+    }
+}
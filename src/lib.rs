@@ -2,7 +2,12 @@ pub mod client;
 pub mod config;
 pub mod daemon;
 pub mod detect;
+pub mod health;
 pub mod logger;
+pub mod logs;
+pub mod pool;
+pub mod ports;
+pub mod privileges;
 pub mod process;
 pub mod proxy;
 pub mod tls;
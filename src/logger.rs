@@ -70,14 +70,14 @@ mod tests {
 
     #[test]
     fn test_log_format_with_multiple_args() {
-        let formatted = format!("[unport] {}", format!("Port {} assigned to {}", 4000, "api.localhost"));
+        let formatted = format!("[unport] {}", format_args!("Port {} assigned to {}", 4000, "api.localhost"));
         assert_eq!(formatted, "[unport] Port 4000 assigned to api.localhost");
     }
 
     #[test]
     fn test_log_format_with_debug_type() {
         let path = std::path::PathBuf::from("/home/user/.unport");
-        let formatted = format!("[unport] {}", format!("Path: {:?}", path));
+        let formatted = format!("[unport] {}", format_args!("Path: {:?}", path));
         assert!(formatted.contains("[unport]"));
         assert!(formatted.contains(".unport"));
     }
@@ -90,7 +90,7 @@ mod tests {
 
     #[test]
     fn test_log_format_special_characters() {
-        let formatted = format!("[unport] {}", format!("URL: https://api.localhost:443/path?q=1&b=2"));
+        let formatted = format!("[unport] {}", format_args!("URL: https://api.localhost:443/path?q=1&b=2"));
         assert!(formatted.contains("https://"));
         assert!(formatted.contains("?q=1&b=2"));
     }
@@ -110,7 +110,7 @@ mod tests {
     #[test]
     fn test_log_format_with_error() {
         let error = std::io::Error::new(std::io::ErrorKind::NotFound, "File not found");
-        let formatted = format!("[unport] {}", format!("Error: {}", error));
+        let formatted = format!("[unport] {}", format_args!("Error: {}", error));
         assert!(formatted.contains("[unport]"));
         assert!(formatted.contains("File not found"));
     }
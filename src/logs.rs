@@ -0,0 +1,370 @@
+//! Service output kept by the daemon
+//!
+//! Each service gets a ring buffer of its most recent lines, which `unport
+//! logs` and the dashboard read, and a log file under ~/.unport/logs/ that is
+//! rotated once it grows too big. Followers subscribe to new lines as they
+//! come in.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::types::{logs_dir, LogLine, LogStream};
+
+/// Lines kept in memory per service
+const RING_CAPACITY: usize = 1000;
+
+/// Size at which a log file is rotated
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Rotated files kept per service (`<domain>.log.1` is the newest)
+const ROTATED_FILES: u32 = 3;
+
+/// Lines a slow follower may fall behind before it skips ahead
+const FOLLOW_BACKLOG: usize = 256;
+
+/// A line with its position in the service's output
+pub type SequencedLine = (u64, LogLine);
+
+/// Output of all services, in memory and on disk
+pub struct LogStore {
+    dir: PathBuf,
+    services: Mutex<HashMap<String, ServiceLog>>,
+}
+
+struct ServiceLog {
+    lines: VecDeque<SequencedLine>,
+    next_seq: u64,
+    file: Option<LogFile>,
+    followers: broadcast::Sender<SequencedLine>,
+}
+
+impl ServiceLog {
+    fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            next_seq: 0,
+            file: None,
+            followers: broadcast::channel(FOLLOW_BACKLOG).0,
+        }
+    }
+}
+
+impl Default for LogStore {
+    fn default() -> Self {
+        Self::new(logs_dir())
+    }
+}
+
+impl LogStore {
+    /// Store writing log files to `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Path of a service's current log file
+    ///
+    /// `None` unless the domain is a plain file name, so it can't point
+    /// outside the log directory.
+    pub fn path(&self, domain: &str) -> Option<PathBuf> {
+        let name = format!("{}.log", domain);
+        let mut components = Path::new(&name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Some(self.dir.join(name)),
+            _ => None,
+        }
+    }
+
+    /// Add a line to a service's log
+    pub fn append(&self, domain: &str, line: LogLine) {
+        let mut services = self.services.lock().unwrap();
+        let log = services.entry(domain.to_string()).or_insert_with(ServiceLog::new);
+
+        if log.file.is_none() {
+            match self.path(domain).map(LogFile::open) {
+                Some(Ok(file)) => log.file = Some(file),
+                Some(Err(e)) => warn!("Failed to open log for {}: {}", domain, e),
+                None => warn!("Not writing a log file for invalid domain {:?}", domain),
+            }
+        }
+        if let Some(file) = &mut log.file {
+            if let Err(e) = file.write_line(&line.text) {
+                warn!("Failed to write log for {}: {}", domain, e);
+                log.file = None;
+            }
+        }
+
+        let seq = log.next_seq;
+        log.next_seq += 1;
+        if log.lines.len() == RING_CAPACITY {
+            log.lines.pop_front();
+        }
+        log.lines.push_back((seq, line.clone()));
+        // Nobody following is fine
+        let _ = log.followers.send((seq, line));
+    }
+
+    /// Recent lines of a service, oldest first, and the sequence number of
+    /// the next line
+    ///
+    /// `since` leaves out lines from before that Unix time (milliseconds).
+    /// Returns `None` if the service never logged anything.
+    pub fn lines(&self, domain: &str, since: Option<u64>) -> Option<(Vec<LogLine>, u64)> {
+        let services = self.services.lock().unwrap();
+        let log = services.get(domain).filter(|log| log.next_seq > 0)?;
+        let lines = log
+            .lines
+            .iter()
+            .filter(|(_, line)| since.is_none_or(|since| line.time >= since))
+            .map(|(_, line)| line.clone())
+            .collect();
+        Some((lines, log.next_seq))
+    }
+
+    /// Receive a service's lines as they are appended
+    pub fn subscribe(&self, domain: &str) -> broadcast::Receiver<SequencedLine> {
+        let mut services = self.services.lock().unwrap();
+        services
+            .entry(domain.to_string())
+            .or_insert_with(ServiceLog::new)
+            .followers
+            .subscribe()
+    }
+}
+
+/// Copy lines from a process's output into the store until it closes
+pub fn capture<R>(logs: Arc<LogStore>, domain: String, stream: LogStream, output: R)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(output);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => logs.append(&domain, log_line(stream, &buf)),
+            }
+        }
+    });
+}
+
+/// Build a log line from raw output, stamped with the current time
+pub fn log_line(stream: LogStream, raw: &[u8]) -> LogLine {
+    let text = String::from_utf8_lossy(raw);
+    LogLine {
+        time: unix_millis(SystemTime::now()),
+        stream,
+        text: text.trim_end_matches(['\n', '\r']).to_string(),
+    }
+}
+
+/// Milliseconds since the Unix epoch
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Parse a duration like "30s", "5m", "2h" or "1d"
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}' (expected e.g. 30s, 5m, 2h)", value))?;
+    let secs = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{}' in '{}' (use s, m, h or d)", unit, value)),
+    };
+    Ok(Duration::from_secs(number * secs))
+}
+
+/// A log file that rotates itself once it reaches `MAX_FILE_SIZE`
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn write_line(&mut self, text: &str) -> std::io::Result<()> {
+        let len = text.len() as u64 + 1;
+        if self.size > 0 && self.size + len > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", text)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift `<domain>.log.N` up by one, dropping the oldest, and start afresh
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for n in (1..ROTATED_FILES).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(time: u64, text: &str) -> LogLine {
+        LogLine {
+            time,
+            stream: LogStream::Stdout,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_append_writes_buffer_and_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        store.append("api.localhost", line(1, "first"));
+        store.append("api.localhost", line(2, "second"));
+
+        let (lines, next) = store.lines("api.localhost", None).unwrap();
+        assert_eq!(lines, vec![line(1, "first"), line(2, "second")]);
+        assert_eq!(next, 2);
+        let file = std::fs::read_to_string(dir.path().join("api.localhost.log")).unwrap();
+        assert_eq!(file, "first\nsecond\n");
+        assert!(store.lines("other.localhost", None).is_none());
+    }
+
+    #[test]
+    fn test_path_stays_in_log_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        assert_eq!(
+            store.path("api.localhost"),
+            Some(dir.path().join("api.localhost.log"))
+        );
+        assert_eq!(store.path("../../home/victim/.bashrc-x"), None);
+        assert_eq!(store.path("/etc/cron.d/x"), None);
+        assert_eq!(store.path("a/b"), None);
+
+        store.append("../escape", line(1, "nope"));
+        assert!(!dir.path().parent().unwrap().join("escape.log").exists());
+    }
+
+    #[test]
+    fn test_lines_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        for time in 1..=5 {
+            store.append("api.localhost", line(time * 1000, &time.to_string()));
+        }
+
+        let (lines, _) = store.lines("api.localhost", Some(4000)).unwrap();
+        assert_eq!(lines, vec![line(4000, "4"), line(5000, "5")]);
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_latest_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        for i in 0..RING_CAPACITY + 10 {
+            store.append("api.localhost", line(0, &i.to_string()));
+        }
+
+        let (lines, next) = store.lines("api.localhost", None).unwrap();
+        assert_eq!(lines.len(), RING_CAPACITY);
+        assert_eq!(lines[0].text, "10");
+        assert_eq!(next, (RING_CAPACITY + 10) as u64);
+    }
+
+    #[test]
+    fn test_log_file_rotates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.localhost.log");
+        std::fs::write(&path, vec![b'x'; MAX_FILE_SIZE as usize]).unwrap();
+        std::fs::write(rotated_path(&path, 1), "older").unwrap();
+
+        let mut file = LogFile::open(path.clone()).unwrap();
+        file.write_line("fresh").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fresh\n");
+        assert_eq!(
+            std::fs::metadata(rotated_path(&path, 1)).unwrap().len(),
+            MAX_FILE_SIZE
+        );
+        assert_eq!(std::fs::read_to_string(rotated_path(&path, 2)).unwrap(), "older");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_new_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LogStore::new(dir.path().to_path_buf());
+        store.append("api.localhost", line(1, "before"));
+
+        let mut rx = store.subscribe("api.localhost");
+        store.append("api.localhost", line(2, "after"));
+        assert_eq!(rx.recv().await.unwrap(), (1, line(2, "after")));
+    }
+
+    #[tokio::test]
+    async fn test_capture_splits_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(LogStore::new(dir.path().to_path_buf()));
+        let mut rx = store.subscribe("api.localhost");
+
+        let output: &'static [u8] = b"one\r\ntwo\npartial";
+        capture(store.clone(), "api.localhost".to_string(), LogStream::Stderr, output);
+
+        let mut texts = Vec::new();
+        for _ in 0..3 {
+            let (_, line) = rx.recv().await.unwrap();
+            assert_eq!(line.stream, LogStream::Stderr);
+            texts.push(line.text);
+        }
+        assert_eq!(texts, vec!["one", "two", "partial"]);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("5 minutes").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+}
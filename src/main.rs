@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
-use unport_cli::{client, daemon, logger, tls};
+use std::net::SocketAddr;
+use std::time::Duration;
+use unport_cli::{client, daemon, logger, logs, tls, types};
 
 #[derive(Parser)]
 #[command(name = "unport")]
@@ -17,7 +19,11 @@ enum Commands {
         action: DaemonAction,
     },
     /// Start the app in current directory and register with daemon
-    Start,
+    Start {
+        /// Let the daemon run the app in the background
+        #[arg(short = 'd', long = "detach")]
+        detach: bool,
+    },
     /// Stop a running service by domain
     Stop {
         /// Domain name to stop
@@ -25,6 +31,17 @@ enum Commands {
     },
     /// List all registered services
     List,
+    /// Show a service's output
+    Logs {
+        /// Domain name of the service
+        domain: String,
+        /// Keep printing new output as it comes in
+        #[arg(short = 'f', long)]
+        follow: bool,
+        /// Only show output from this long ago on (e.g. 30s, 5m, 2h)
+        #[arg(long, value_name = "DURATION", value_parser = logs::parse_duration)]
+        since: Option<Duration>,
+    },
     /// Add unport CA to system trust store for HTTPS support
     TrustCa {
         /// Remove CA from trust store instead of adding
@@ -47,9 +64,22 @@ enum DaemonAction {
         /// Enable HTTPS on port 443
         #[arg(long)]
         https: bool,
+        /// HTTP listen address, repeatable (PORT, IP or IP:PORT; default 127.0.0.1:80)
+        #[arg(long = "http-addr", value_name = "ADDR", value_parser = types::parse_http_addr)]
+        http_addr: Vec<SocketAddr>,
+        /// HTTPS listen address, repeatable (PORT, IP or IP:PORT; default 127.0.0.1:443)
+        #[arg(
+            long = "https-addr",
+            value_name = "ADDR",
+            value_parser = types::parse_https_addr,
+            requires = "https"
+        )]
+        https_addr: Vec<SocketAddr>,
     },
     /// Stop the daemon
     Stop,
+    /// Re-read ~/.unport/config.json without restarting the daemon
+    Reload,
     /// Show daemon status
     Status,
 }
@@ -62,13 +92,31 @@ async fn main() -> anyhow::Result<()> {
 
     match cli.command {
         Commands::Daemon { action } => match action {
-            DaemonAction::Start { detach, https } => daemon::run(detach, https).await,
+            DaemonAction::Start {
+                detach,
+                https,
+                http_addr,
+                https_addr,
+            } => {
+                let args = types::ListenArgs {
+                    https,
+                    http_addrs: http_addr,
+                    https_addrs: https_addr,
+                };
+                daemon::run(detach, args).await
+            }
             DaemonAction::Stop => client::stop_daemon().await,
+            DaemonAction::Reload => client::reload_daemon().await,
             DaemonAction::Status => client::daemon_status().await,
         },
-        Commands::Start => client::start().await,
+        Commands::Start { detach } => client::start(detach).await,
         Commands::Stop { domain } => client::stop_service(&domain).await,
         Commands::List => client::list().await,
+        Commands::Logs {
+            domain,
+            follow,
+            since,
+        } => client::logs(&domain, follow, since).await,
         Commands::TrustCa { remove } => client::trust_ca(remove).await,
         Commands::CleanCerts => tls::clean_certs(),
         Commands::RegenCert => client::regen_cert().await,
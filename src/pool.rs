@@ -0,0 +1,394 @@
+//! Keep-alive connection pool for proxied backends
+//!
+//! Connections are kept per backend port. HTTP/1.1 connections go back to the
+//! pool once their response has been fully read; an HTTP/2 connection is
+//! shared by all requests to its backend. The address a backend was last
+//! reached on is remembered, so `localhost` is only resolved again when that
+//! address stops answering.
+
+use anyhow::Result;
+use hyper::body::Incoming;
+use hyper::client::conn::{http1, http2};
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::error;
+
+/// How long an idle connection is kept before it is dropped
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Maximum number of idle HTTP/1.1 connections kept per backend
+const MAX_IDLE_PER_BACKEND: usize = 32;
+
+/// How often to retry a backend that isn't listening yet
+const LISTEN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pooled connections to every backend, keyed by port
+#[derive(Default)]
+pub struct BackendPool {
+    backends: Mutex<HashMap<u16, Backend>>,
+}
+
+#[derive(Default)]
+struct Backend {
+    /// Address the backend was last reached on
+    addr: Option<SocketAddr>,
+    /// Idle HTTP/1.1 connections, most recently used last
+    idle: Vec<IdleConnection>,
+    /// Shared HTTP/2 connection
+    h2: Option<http2::SendRequest<Incoming>>,
+}
+
+struct IdleConnection {
+    sender: http1::SendRequest<Incoming>,
+    idle_since: Instant,
+}
+
+impl BackendPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send a request over a pooled (or new) HTTP/1.1 connection
+    pub async fn send_http1(
+        self: &Arc<Self>,
+        port: u16,
+        req: Request<Incoming>,
+    ) -> Result<Response<Incoming>> {
+        let mut req = req;
+
+        // A pooled connection may have been closed by the backend in the
+        // meantime; if the request was not written yet, retry on a new one
+        while let Some(mut sender) = self.checkout_http1(port) {
+            match sender.try_send_request(req).await {
+                Ok(response) => {
+                    self.release_http1(port, sender);
+                    return Ok(response);
+                }
+                Err(mut e) => match e.take_message() {
+                    Some(message) => req = message,
+                    None => return Err(e.into_error().into()),
+                },
+            }
+        }
+
+        let mut sender = self.connect_http1(port).await?;
+        let response = sender.send_request(req).await?;
+        self.release_http1(port, sender);
+        Ok(response)
+    }
+
+    /// Send a request over the shared (or a new) HTTP/2 connection
+    pub async fn send_h2(&self, port: u16, req: Request<Incoming>) -> Result<Response<Incoming>> {
+        let existing = self
+            .backends
+            .lock()
+            .unwrap()
+            .get(&port)
+            .and_then(|b| b.h2.clone())
+            .filter(|sender| !sender.is_closed());
+
+        let mut sender = match existing {
+            Some(sender) => sender,
+            None => {
+                let sender = self.connect_h2(port).await?;
+                self.backends.lock().unwrap().entry(port).or_default().h2 = Some(sender.clone());
+                sender
+            }
+        };
+
+        Ok(sender.send_request(req).await?)
+    }
+
+    /// Wait until a backend accepts connections, or until `deadline`
+    ///
+    /// Returns true as soon as a connection succeeds (or one is already
+    /// pooled), false if the backend still isn't listening at the deadline.
+    /// The connection that succeeded is kept for the next request, as HTTP/2
+    /// if `h2` is set.
+    pub async fn wait_until_listening(&self, port: u16, deadline: Instant, h2: bool) -> bool {
+        let pooled = self
+            .backends
+            .lock()
+            .unwrap()
+            .get(&port)
+            .is_some_and(|b| !b.idle.is_empty() || b.h2.is_some());
+        if pooled {
+            return true;
+        }
+
+        loop {
+            if let Ok(stream) = self.connect(port).await {
+                if h2 {
+                    if let Ok(sender) = handshake_h2(stream).await {
+                        self.backends.lock().unwrap().entry(port).or_default().h2 = Some(sender);
+                    }
+                } else if let Ok(mut sender) = handshake_http1(stream).await {
+                    if sender.ready().await.is_ok() {
+                        self.push_idle(port, sender);
+                    }
+                }
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            tokio::time::sleep(LISTEN_POLL_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    /// Drop all connections and the remembered address for a backend
+    pub fn evict(&self, port: u16) {
+        self.backends.lock().unwrap().remove(&port);
+    }
+
+    /// Number of idle HTTP/1.1 connections held for a backend
+    pub fn idle_count(&self, port: u16) -> usize {
+        self.backends
+            .lock()
+            .unwrap()
+            .get(&port)
+            .map(|b| b.idle.len())
+            .unwrap_or(0)
+    }
+
+    /// Take a usable idle connection, discarding stale ones along the way
+    fn checkout_http1(&self, port: u16) -> Option<http1::SendRequest<Incoming>> {
+        let mut backends = self.backends.lock().unwrap();
+        let backend = backends.get_mut(&port)?;
+
+        while let Some(conn) = backend.idle.pop() {
+            if conn.idle_since.elapsed() < IDLE_TIMEOUT && conn.sender.is_ready() {
+                return Some(conn.sender);
+            }
+        }
+        None
+    }
+
+    /// Return a connection to the pool once its current response is finished
+    fn release_http1(self: &Arc<Self>, port: u16, mut sender: http1::SendRequest<Incoming>) {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            // Resolves when the response body has been read; errors if the
+            // connection was closed or upgraded instead
+            if sender.ready().await.is_err() {
+                return;
+            }
+            let Some(pool) = pool.upgrade() else {
+                return;
+            };
+            // Backend was evicted while the request was in flight
+            if pool.backends.lock().unwrap().contains_key(&port) {
+                pool.push_idle(port, sender);
+            }
+        });
+    }
+
+    /// Keep a ready connection for later requests
+    fn push_idle(&self, port: u16, sender: http1::SendRequest<Incoming>) {
+        let mut backends = self.backends.lock().unwrap();
+        let backend = backends.entry(port).or_default();
+        backend.idle.retain(|c| c.idle_since.elapsed() < IDLE_TIMEOUT);
+        if backend.idle.len() < MAX_IDLE_PER_BACKEND {
+            backend.idle.push(IdleConnection {
+                sender,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    async fn connect_http1(&self, port: u16) -> Result<http1::SendRequest<Incoming>> {
+        handshake_http1(self.connect(port).await?).await
+    }
+
+    async fn connect_h2(&self, port: u16) -> Result<http2::SendRequest<Incoming>> {
+        handshake_h2(self.connect(port).await?).await
+    }
+
+    /// Open a TCP connection to a backend, preferring the address that worked last time
+    async fn connect(&self, port: u16) -> Result<TcpStream> {
+        let known = self.backends.lock().unwrap().get(&port).and_then(|b| b.addr);
+        if let Some(addr) = known {
+            if let Ok(stream) = TcpStream::connect(addr).await {
+                return Ok(stream);
+            }
+        }
+
+        // Try localhost (which resolves to IPv4 or IPv6) first, then both loopbacks explicitly
+        let stream = match TcpStream::connect(format!("localhost:{}", port)).await {
+            Ok(s) => s,
+            Err(_) => match TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+                Ok(s) => s,
+                Err(_) => TcpStream::connect(format!("[::1]:{}", port)).await?,
+            },
+        };
+
+        if let Ok(addr) = stream.peer_addr() {
+            self.backends.lock().unwrap().entry(port).or_default().addr = Some(addr);
+        }
+        Ok(stream)
+    }
+
+    #[cfg(test)]
+    fn remembered_addr(&self, port: u16) -> Option<SocketAddr> {
+        self.backends.lock().unwrap().get(&port).and_then(|b| b.addr)
+    }
+}
+
+async fn handshake_http1(stream: TcpStream) -> Result<http1::SendRequest<Incoming>> {
+    let (sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = conn.with_upgrades().await {
+            error!("Connection error: {}", e);
+        }
+    });
+
+    Ok(sender)
+}
+
+async fn handshake_h2(stream: TcpStream) -> Result<http2::SendRequest<Incoming>> {
+    let (sender, conn) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            error!("HTTP/2 backend connection error: {}", e);
+        }
+    });
+
+    Ok(sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_evict_forgets_backend() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let pool = BackendPool::new();
+        let _stream = pool.connect(port).await.unwrap();
+        assert_eq!(pool.remembered_addr(port), Some(listener.local_addr().unwrap()));
+
+        pool.evict(port);
+        assert_eq!(pool.remembered_addr(port), None);
+        assert_eq!(pool.idle_count(port), 0);
+    }
+
+    #[tokio::test]
+    async fn test_connect_remembers_address_family() {
+        // Backend only listening on IPv6 loopback
+        let listener = match TcpListener::bind("[::1]:0").await {
+            Ok(l) => l,
+            Err(_) => return, // IPv6 not available in this environment
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        let pool = BackendPool::new();
+        let _stream = pool.connect(port).await.unwrap();
+        assert!(pool.remembered_addr(port).unwrap().is_ipv6());
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_listening() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        // Backend that only starts listening after a while
+        let backend = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            let _ = listener.accept().await;
+        });
+
+        let pool = BackendPool::new();
+        let started = Instant::now();
+        assert!(pool.wait_until_listening(port, started + Duration::from_secs(5), false).await);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        // The connection is handed to the next request
+        assert_eq!(pool.idle_count(port), 1);
+        backend.abort();
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_listening_gives_up() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let pool = BackendPool::new();
+        let deadline = Instant::now() + Duration::from_millis(300);
+        assert!(!pool.wait_until_listening(port, deadline, false).await);
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_connection_is_reused() {
+        // Backend that counts accepted connections and serves keep-alive responses
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        let _ = stream
+                            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                            .await;
+                    }
+                });
+            }
+        });
+
+        // Client requests arrive through a real hyper server so bodies are `Incoming`
+        let pool = Arc::new(BackendPool::new());
+        let front = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let front_addr = front.local_addr().unwrap();
+        let server_pool = pool.clone();
+        tokio::spawn(async move {
+            let (stream, _) = front.accept().await.unwrap();
+            let service = hyper::service::service_fn(move |req: Request<Incoming>| {
+                let pool = server_pool.clone();
+                async move { pool.send_http1(port, req).await }
+            });
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let mut client = TcpStream::connect(front_addr).await.unwrap();
+        let mut buf = [0u8; 1024];
+        for _ in 0..3 {
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: app.localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&buf[..n]).ends_with("ok"));
+            // Give the release task a moment to return the connection
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(pool.idle_count(port), 1);
+    }
+}
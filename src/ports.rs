@@ -0,0 +1,252 @@
+//! Detection of the ports services actually listen on
+//!
+//! Apps that ignore `PORT` (or that `detect` guessed wrong) bind some other
+//! port, and every request to the route they were assigned fails. The daemon
+//! looks up the listening TCP sockets of each service's process tree and
+//! warns when the assigned port isn't among them, or moves the route there if
+//! the service set `autoPort`. Only Linux has the `/proc` files this needs;
+//! elsewhere nothing is ever found.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::log_info;
+
+use crate::process::{self, read_stat};
+use crate::proxy::SharedRegistry;
+
+/// How often the listening ports are looked up
+const INTERVAL: Duration = Duration::from_secs(2);
+
+/// TCP state of a listening socket in `/proc/net/tcp`
+const TCP_LISTEN: &str = "0A";
+
+/// What to do about the ports a service's app listens on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortCheck {
+    /// Listening on the assigned port, or on nothing yet
+    Fine,
+    /// Listening elsewhere; the route should move to this port
+    Move(u16),
+    /// Listening elsewhere, but the route stays where it is
+    Warn,
+}
+
+/// Compare the assigned port with the ones the app listens on
+///
+/// The route only moves when the service opted in and there's a single
+/// candidate; with several (e.g. a dev server and a debugger) it can't tell.
+pub fn check(assigned: u16, listening: &[u16], auto_port: bool) -> PortCheck {
+    match listening {
+        [] => PortCheck::Fine,
+        ports if ports.contains(&assigned) => PortCheck::Fine,
+        [port] if auto_port => PortCheck::Move(*port),
+        _ => PortCheck::Warn,
+    }
+}
+
+/// Check every registered service's ports, forever
+pub async fn run(registry: SharedRegistry) {
+    // What was last reported for each service, so each mismatch is logged once
+    let mut reported: HashMap<String, (u32, Vec<u16>)> = HashMap::new();
+
+    loop {
+        tokio::time::sleep(INTERVAL).await;
+
+        let services = registry.read().await.list();
+        reported.retain(|domain, _| services.iter().any(|s| &s.domain == domain));
+
+        let scanned = tokio::task::spawn_blocking(move || {
+            services
+                .into_iter()
+                .filter(process::is_service_alive)
+                .map(|s| {
+                    let ports = listening_ports(s.pid);
+                    (s, ports)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        for (service, ports) in scanned {
+            let domain = service.domain;
+            match check(service.port, &ports, service.options.auto_port) {
+                PortCheck::Fine => {
+                    reported.remove(&domain);
+                }
+                PortCheck::Move(port) => {
+                    // Unless the service was restarted or replaced meanwhile
+                    let mut reg = registry.write().await;
+                    let current = reg.get(&domain).filter(|s| s.pid == service.pid);
+                    if current.is_some_and(|s| s.port == service.port) {
+                        reg.set_port(&domain, port);
+                        log_info!(
+                            "{} listens on port {} instead of {}, moved its route",
+                            domain,
+                            port,
+                            service.port
+                        );
+                    }
+                }
+                PortCheck::Warn => {
+                    let seen = (service.pid, ports);
+                    if reported.get(&domain) == Some(&seen) {
+                        continue;
+                    }
+                    let noun = if seen.1.len() == 1 { "port" } else { "ports" };
+                    let list = seen.1.iter().map(u16::to_string).collect::<Vec<_>>().join(", ");
+                    if service.options.auto_port {
+                        warn!(
+                            "{} listens on {} {} but not on its assigned port {}, leaving the route",
+                            domain, noun, list, service.port
+                        );
+                    } else {
+                        warn!(
+                            "{} listens on {} {} instead of its assigned port {}; set \"autoPort\": true in unport.json to route there",
+                            domain, noun, list, service.port
+                        );
+                    }
+                    reported.insert(domain, seen);
+                }
+            }
+        }
+    }
+}
+
+/// TCP ports the process tree of `pid` listens on, sorted
+pub fn listening_ports(pid: u32) -> Vec<u16> {
+    let inodes = socket_inodes(&process_tree(pid));
+    if inodes.is_empty() {
+        return Vec::new();
+    }
+
+    let mut ports: Vec<u16> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(table).ok())
+        .flat_map(|content| parse_listening(&content))
+        .filter(|(_, inode)| inodes.contains(inode))
+        .map(|(port, _)| port)
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+/// `pid`, its descendants, and anything else left in its process group
+///
+/// Start scripts often fork the actual server, which may outlive them.
+pub fn process_tree(pid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![pid];
+    };
+    let stats: Vec<(u32, u32, u32)> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter_map(|p| read_stat(p).map(|stat| (p, stat.ppid, stat.pgrp)))
+        .collect();
+
+    let mut tree = vec![pid];
+    loop {
+        let found: Vec<u32> = stats
+            .iter()
+            .filter(|(p, ppid, pgrp)| {
+                !tree.contains(p) && (tree.contains(ppid) || *pgrp == pid)
+            })
+            .map(|(p, _, _)| *p)
+            .collect();
+        if found.is_empty() {
+            return tree;
+        }
+        tree.extend(found);
+    }
+}
+
+/// Inodes of the sockets the given processes have open
+///
+/// Processes of other users are skipped, their `fd` directory isn't readable.
+fn socket_inodes(pids: &[u32]) -> HashSet<u64> {
+    pids.iter()
+        .filter_map(|pid| std::fs::read_dir(format!("/proc/{}/fd", pid)).ok())
+        .flat_map(|fds| fds.flatten())
+        .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+        .filter_map(|target| {
+            let target = target.to_str()?;
+            target.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+        })
+        .collect()
+}
+
+/// Parse `/proc/net/tcp` or `/proc/net/tcp6` into (port, inode) pairs of the
+/// listening sockets
+pub fn parse_listening(content: &str) -> Vec<(u16, u64)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if *fields.get(3)? != TCP_LISTEN {
+                return None;
+            }
+            let (_, port) = fields.get(1)?.rsplit_once(':')?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            Some((port, inode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41527 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:0FA0 0100007F:D2A4 01 00000000:00000000 00:00000000 00000000  1000        0 41800 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 12001 1 0000000000000000 100 0 0 10 0
+";
+
+    #[test]
+    fn test_parse_listening() {
+        // The established connection on 4000 isn't listening
+        assert_eq!(parse_listening(TCP), vec![(3000, 41527), (8080, 12001)]);
+    }
+
+    #[test]
+    fn test_parse_listening_tcp6() {
+        let tcp6 = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000001000000:1389 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 55012 1 0000000000000000 100 0 0 10 0
+";
+        assert_eq!(parse_listening(tcp6), vec![(5001, 55012)]);
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(4000, &[], true), PortCheck::Fine);
+        assert_eq!(check(4000, &[4000, 9229], false), PortCheck::Fine);
+        assert_eq!(check(4000, &[3000], false), PortCheck::Warn);
+        assert_eq!(check(4000, &[3000], true), PortCheck::Move(3000));
+        // Can't tell which one is the app
+        assert_eq!(check(4000, &[3000, 9229], true), PortCheck::Warn);
+    }
+
+    #[test]
+    fn test_listening_ports_finds_own_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(listening_ports(std::process::id()).contains(&port));
+    }
+
+    #[test]
+    fn test_process_tree_includes_children() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let tree = process_tree(std::process::id());
+        assert_eq!(tree[0], std::process::id());
+        assert!(tree.contains(&child.id()));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
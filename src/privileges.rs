@@ -0,0 +1,176 @@
+//! Running the daemon without root
+//!
+//! The daemon only needs root (or CAP_NET_BIND_SERVICE) to bind ports below
+//! 1024. When started through sudo it binds its listeners first and then
+//! switches to the invoking user, so everything under ~/.unport belongs to
+//! that user and apps are signalled with that user's rights.
+
+use anyhow::{Context, Result};
+use std::ffi::{CStr, CString};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// The user who ran `sudo unport ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvokingUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// The user behind sudo, if we're running as root (or already switched to them)
+pub fn invoking_user() -> Option<InvokingUser> {
+    let (uid, gid) = parse_sudo_ids(
+        std::env::var("SUDO_UID").ok().as_deref(),
+        std::env::var("SUDO_GID").ok().as_deref(),
+        unsafe { libc::getuid() },
+    )?;
+    let (name, home) = lookup_user(uid)?;
+    Some(InvokingUser {
+        name,
+        uid,
+        gid,
+        home,
+    })
+}
+
+/// Parse SUDO_UID/SUDO_GID, ignoring them unless the current user is root
+/// or the sudo user itself (a stale environment must not redirect state)
+fn parse_sudo_ids(uid: Option<&str>, gid: Option<&str>, current_uid: u32) -> Option<(u32, u32)> {
+    let uid: u32 = uid?.trim().parse().ok()?;
+    let gid: u32 = gid?.trim().parse().ok()?;
+    if uid == 0 || (current_uid != 0 && current_uid != uid) {
+        return None;
+    }
+    Some((uid, gid))
+}
+
+/// Home directory of the real user, even when running under sudo
+pub fn home_dir() -> Option<PathBuf> {
+    invoking_user()
+        .map(|user| user.home)
+        .or_else(dirs::home_dir)
+}
+
+/// Look up a user's name and home directory in the password database
+fn lookup_user(uid: u32) -> Option<(String, PathBuf)> {
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() || pwd.pw_name.is_null() || pwd.pw_dir.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    Some((
+        name.to_string_lossy().into_owned(),
+        PathBuf::from(dir.to_string_lossy().into_owned()),
+    ))
+}
+
+/// Whether the process runs with root privileges
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Switch from root to the sudo user
+///
+/// Returns the user we switched to, or `None` when there's nothing to drop
+/// (not root, or root without sudo).
+pub fn drop_privileges() -> Result<Option<InvokingUser>> {
+    if !is_root() {
+        return Ok(None);
+    }
+    let Some(user) = invoking_user() else {
+        return Ok(None);
+    };
+
+    let name = CString::new(user.name.as_str()).context("Invalid user name")?;
+    unsafe {
+        // The user's supplementary groups, so group-owned project files stay accessible
+        if libc::initgroups(name.as_ptr(), user.gid as _) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set groups");
+        }
+        if libc::setgid(user.gid) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set group ID");
+        }
+        if libc::setuid(user.uid) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set user ID");
+        }
+        // Make sure root can't be regained
+        if libc::setuid(0) == 0 {
+            anyhow::bail!("Failed to drop root privileges");
+        }
+    }
+
+    Ok(Some(user))
+}
+
+/// Hand root-owned files under `path` back to the sudo user
+///
+/// Older versions ran the daemon as root the whole time, and a detached start
+/// creates the log before the daemon drops privileges.
+pub fn restore_ownership(path: &Path) -> Result<()> {
+    if !is_root() {
+        return Ok(());
+    }
+    let Some(user) = invoking_user() else {
+        return Ok(());
+    };
+    chown_root_owned(path, &user)
+}
+
+fn chown_root_owned(path: &Path, user: &InvokingUser) -> Result<()> {
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return Ok(()),
+    };
+    if meta.uid() == 0 {
+        std::os::unix::fs::lchown(path, Some(user.uid), Some(user.gid))
+            .with_context(|| format!("Failed to change owner of {:?}", path))?;
+    }
+    if meta.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_root_owned(&entry?.path(), user)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sudo_ids_as_root() {
+        assert_eq!(parse_sudo_ids(Some("1000"), Some("1000"), 0), Some((1000, 1000)));
+    }
+
+    #[test]
+    fn test_parse_sudo_ids_after_drop() {
+        assert_eq!(parse_sudo_ids(Some("1000"), Some("100"), 1000), Some((1000, 100)));
+    }
+
+    #[test]
+    fn test_parse_sudo_ids_ignores_other_users() {
+        assert_eq!(parse_sudo_ids(Some("1000"), Some("1000"), 1001), None);
+    }
+
+    #[test]
+    fn test_parse_sudo_ids_ignores_root_and_garbage() {
+        assert_eq!(parse_sudo_ids(Some("0"), Some("0"), 0), None);
+        assert_eq!(parse_sudo_ids(Some("abc"), Some("1000"), 0), None);
+        assert_eq!(parse_sudo_ids(None, Some("1000"), 0), None);
+        assert_eq!(parse_sudo_ids(Some("1000"), None, 0), None);
+    }
+
+    #[test]
+    fn test_lookup_user_for_current_user() {
+        let uid = unsafe { libc::getuid() };
+        let (name, _) = lookup_user(uid).unwrap();
+        assert!(!name.is_empty());
+    }
+}
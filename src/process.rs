@@ -1,7 +1,10 @@
 use anyhow::{Context, Result};
+use std::os::unix::process::CommandExt;
 use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
 
 use crate::detect::PortStrategy;
+use crate::types::{AppCommand, ProcessIdentity, Service};
 
 /// Spawn an app process with port injection
 pub fn spawn_app(
@@ -11,46 +14,245 @@ pub fn spawn_app(
     port_env_override: Option<&str>,
     port_arg_override: Option<&str>,
 ) -> Result<Child> {
-    let mut parts: Vec<&str> = command.split_whitespace().collect();
+    let app = app_command(command, port, port_strategy, port_env_override, port_arg_override)?;
+
+    let mut cmd = Command::new(&app.program);
+    cmd.args(&app.args);
+    cmd.envs(app.env);
+    cmd.stdin(Stdio::null());
+    // Piped so the output can be copied to the daemon's log as well
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    // Own process group, so stopping the service reaches everything it started
+    cmd.process_group(0);
+
+    let child = cmd.spawn().context("Failed to spawn process")?;
+    Ok(child)
+}
+
+/// Resolve the program, arguments and port variables for an app
+///
+/// `env` only holds what unport adds; the caller decides what the app inherits.
+pub fn app_command(
+    command: &str,
+    port: u16,
+    port_strategy: &PortStrategy,
+    port_env_override: Option<&str>,
+    port_arg_override: Option<&str>,
+) -> Result<AppCommand> {
+    let mut parts: Vec<String> = command.split_whitespace().map(String::from).collect();
     if parts.is_empty() {
         anyhow::bail!("Empty command");
     }
 
-    let program = parts.remove(0);
-    let mut cmd = Command::new(program);
-    cmd.args(&parts);
-    cmd.stdin(Stdio::null());
-    cmd.stdout(Stdio::inherit());
-    cmd.stderr(Stdio::inherit());
+    let mut app = AppCommand {
+        program: parts.remove(0),
+        args: parts,
+        env: Default::default(),
+    };
 
     // Apply port injection based on strategy
     match (port_env_override, port_arg_override) {
         // User override: env var
         (Some(env_var), _) => {
-            cmd.env(env_var, port.to_string());
+            app.env.insert(env_var.to_string(), port.to_string());
         }
         // User override: CLI arg
         (_, Some(arg)) => {
-            cmd.arg(arg);
-            cmd.arg(port.to_string());
+            app.args.push(arg.to_string());
+            app.args.push(port.to_string());
         }
         // Auto-detected strategy
         _ => match port_strategy {
             PortStrategy::EnvVar(var) => {
-                cmd.env(var, port.to_string());
+                app.env.insert(var.clone(), port.to_string());
             }
             PortStrategy::CliFlag(flag) => {
                 // Special case for Django: "0.0.0.0:" needs port appended directly
                 if flag.ends_with(':') {
-                    cmd.arg(format!("{}{}", flag, port));
+                    app.args.push(format!("{}{}", flag, port));
                 } else {
-                    cmd.arg(flag);
-                    cmd.arg(port.to_string());
+                    app.args.push(flag.clone());
+                    app.args.push(port.to_string());
                 }
             }
         },
     }
 
-    let child = cmd.spawn().context("Failed to spawn process")?;
-    Ok(child)
+    Ok(app)
+}
+
+/// How often to check whether a stopped service has exited
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a service went away when it was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// Nothing was running any more
+    NotRunning,
+    /// Exited after SIGTERM
+    Exited,
+    /// Still running after the timeout, so it got SIGKILL
+    Killed,
+}
+
+/// What to signal for a service: its process group if it leads one
+/// (services started by unport always do), otherwise just the process
+///
+/// The group outlives its leader, e.g. when `npm` exits but the server it
+/// started keeps running. Our own group is never targeted.
+fn signal_target(pid: u32) -> i32 {
+    let pid = pid as i32;
+    let own_group = unsafe { libc::getpgrp() };
+    let is_group = unsafe { libc::kill(-pid, 0) == 0 };
+    if pid > 1 && pid != own_group && is_group {
+        -pid
+    } else {
+        pid
+    }
+}
+
+/// Send a signal to a service's process group (or the process itself)
+///
+/// Returns false if there was nothing to signal.
+pub fn signal_service(pid: u32, signal: i32) -> bool {
+    unsafe { libc::kill(signal_target(pid), signal) == 0 }
+}
+
+/// Stop a service: SIGTERM to its process group, then SIGKILL if anything is
+/// still running after `timeout`
+///
+/// Nothing is signalled if `pid` now belongs to a different process than `identity`.
+pub async fn terminate(
+    pid: u32,
+    identity: Option<&ProcessIdentity>,
+    timeout: Duration,
+) -> Termination {
+    if !is_same_process(pid, identity) {
+        return Termination::NotRunning;
+    }
+    let target = signal_target(pid);
+    if unsafe { libc::kill(target, libc::SIGTERM) } != 0 {
+        return Termination::NotRunning;
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        tokio::time::sleep(STOP_POLL_INTERVAL).await;
+        if !is_running(target) {
+            return Termination::Exited;
+        }
+    }
+
+    unsafe {
+        libc::kill(target, libc::SIGKILL);
+    }
+    Termination::Killed
+}
+
+/// Whether a process exists
+///
+/// EPERM counts: the process is there, it just belongs to another user.
+pub fn is_alive(pid: u32) -> bool {
+    if unsafe { libc::kill(pid as i32, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Whether a service's process still runs, and isn't an unrelated process
+/// that got its PID after a reboot or once PIDs wrapped
+pub fn is_service_alive(service: &Service) -> bool {
+    is_alive(service.pid) && is_same_process(service.pid, service.identity.as_ref())
+}
+
+/// Read what identifies the process behind a PID (Linux only)
+pub fn identify(pid: u32) -> Option<ProcessIdentity> {
+    let stat = read_stat(pid)?;
+    Some(ProcessIdentity {
+        start_time: stat.start_time,
+        exe: read_exe(pid),
+    })
+}
+
+/// Whether `pid` still belongs to the process `identity` was read from
+///
+/// Only the start time is compared: apps often `exec` into something else
+/// after registering (`sh start.sh` running `exec node`), which changes the
+/// executable but not the process.
+///
+/// Gives the benefit of the doubt when there is nothing to compare, e.g. for
+/// services from older registries or when `/proc` isn't available. A process
+/// group whose leader exited still counts, as its ID can't be reused meanwhile.
+pub fn is_same_process(pid: u32, identity: Option<&ProcessIdentity>) -> bool {
+    let (Some(identity), Some(stat)) = (identity, read_stat(pid)) else {
+        return true;
+    };
+    stat.start_time == identity.start_time
+}
+
+/// Executable of a process, from `/proc/<pid>/exe` (Linux only)
+///
+/// Only readable for processes of the same user (or by root).
+fn read_exe(pid: u32) -> Option<std::path::PathBuf> {
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+    // Rebuilt or upgraded binaries show up as "/path/to/app (deleted)"
+    match exe.to_str().and_then(|path| path.strip_suffix(" (deleted)")) {
+        Some(path) => Some(path.into()),
+        None => Some(exe),
+    }
+}
+
+/// Whether a process (or a process group, if `target` is negative) still runs
+///
+/// Zombies don't count: they hold no ports and only wait to be reaped, which
+/// may never happen if nobody reaps orphans (e.g. in a container).
+fn is_running(target: i32) -> bool {
+    if unsafe { libc::kill(target, 0) } != 0 {
+        return false;
+    }
+    if cfg!(target_os = "linux") {
+        if target > 0 {
+            return read_stat(target as u32).is_some_and(|stat| stat.state != 'Z');
+        }
+        let pgid = target.unsigned_abs();
+        if let Ok(entries) = std::fs::read_dir("/proc") {
+            return entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+                .filter_map(read_stat)
+                .any(|stat| stat.pgrp == pgid && stat.state != 'Z');
+        }
+    }
+    true
+}
+
+/// The parts of `/proc/<pid>/stat` unport looks at
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcStat {
+    /// R (running), S (sleeping), Z (zombie), ...
+    pub state: char,
+    pub ppid: u32,
+    pub pgrp: u32,
+    /// Clock ticks after boot when the process started
+    pub start_time: u64,
+}
+
+/// Read `/proc/<pid>/stat` (Linux only)
+pub fn read_stat(pid: u32) -> Option<ProcStat> {
+    let content = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_stat(&content)
+}
+
+/// Parse the contents of `/proc/<pid>/stat`
+pub fn parse_stat(content: &str) -> Option<ProcStat> {
+    // The command name is in parentheses and may itself contain spaces or ')'
+    let (_, rest) = content.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    Some(ProcStat {
+        state: fields.first()?.chars().next()?,
+        ppid: fields.get(1)?.parse().ok()?,
+        pgrp: fields.get(2)?.parse().ok()?,
+        start_time: fields.get(19)?.parse().ok()?,
+    })
 }
@@ -1,272 +1,532 @@
 use anyhow::{Context, Result};
+use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
-use hyper::server::conn::http1;
+use hyper::server::conn::http2;
 use hyper::service::service_fn;
-use hyper::{body::Incoming, Request, Response};
-use hyper_util::rt::TokioIo;
+use hyper::{body::Incoming, header, Request, Response, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
+use tokio::task::JoinSet;
 use tokio_rustls::TlsAcceptor;
 use tracing::{error, warn};
 
 use crate::log_info;
 
-use crate::daemon::Registry;
-use crate::types::Service;
+use crate::daemon::{stop_service, Registry, Route};
+use crate::pool::BackendPool;
+use crate::process;
+use crate::types::{service_url, Service, UpstreamProtocol};
 
 pub type SharedRegistry = Arc<RwLock<Registry>>;
 pub type SharedTlsAcceptor = Arc<RwLock<TlsAcceptor>>;
 
-/// Run the HTTP proxy server on port 80
-pub async fn run_http(registry: SharedRegistry) -> Result<()> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], 80));
-    let listener = TcpListener::bind(addr).await.context(
-        "Failed to bind to port 80. Try running with sudo or check if another process is using it.",
-    )?;
+/// Response body type used by the proxy: either a streamed backend body or a
+/// locally generated one (dashboard, errors)
+pub type ProxyBody = BoxBody<Bytes, hyper::Error>;
 
-    log_info!("HTTP proxy listening on http://127.0.0.1:80");
+/// Wrap a fully generated body so it can be returned alongside streamed ones
+fn full<T: Into<Bytes>>(chunk: T) -> ProxyBody {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+/// Bind proxy listeners on all given addresses
+///
+/// Binding happens up front so the daemon fails at startup (rather than in a
+/// background task) when a port is taken or needs privileges.
+pub async fn bind(addrs: &[SocketAddr]) -> Result<Vec<TcpListener>> {
+    let mut listeners = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        let listener = TcpListener::bind(addr).await.with_context(|| {
+            format!(
+                "Failed to bind to {}. Ports below 1024 need sudo or CAP_NET_BIND_SERVICE; \
+                 also check if another process is using it.",
+                addr
+            )
+        })?;
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
 
-    loop {
-        let (stream, _) = listener.accept().await?;
+/// Run the HTTP proxy server on the given listeners
+pub async fn run_http(registry: SharedRegistry, listeners: Vec<TcpListener>) -> Result<()> {
+    let mut servers = JoinSet::new();
+    for listener in listeners {
         let registry = registry.clone();
+        servers.spawn(async move {
+            let local = listener.local_addr()?;
+            log_info!("HTTP proxy listening on http://{}", local);
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, registry).await {
-                error!("HTTP connection error: {}", e);
+            loop {
+                let (stream, addr) = listener.accept().await?;
+                let registry = registry.clone();
+                let client = ClientInfo {
+                    addr,
+                    port: local.port(),
+                    tls: false,
+                };
+
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, registry, client).await {
+                        error!("HTTP connection error: {}", e);
+                    }
+                });
             }
         });
     }
+    wait_for_servers(servers).await
 }
 
-/// Run the HTTPS proxy server on port 443
-pub async fn run_https(registry: SharedRegistry, acceptor: SharedTlsAcceptor) -> Result<()> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], 443));
-    let listener = TcpListener::bind(addr).await.context(
-        "Failed to bind to port 443. Try running with sudo or check if another process is using it.",
-    )?;
-
-    log_info!("HTTPS proxy listening on https://127.0.0.1:443");
-
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let acceptor = acceptor.clone();
+/// Run the HTTPS proxy server on the given listeners
+pub async fn run_https(
+    registry: SharedRegistry,
+    acceptor: SharedTlsAcceptor,
+    listeners: Vec<TcpListener>,
+) -> Result<()> {
+    let mut servers = JoinSet::new();
+    for listener in listeners {
         let registry = registry.clone();
+        let acceptor = acceptor.clone();
+        servers.spawn(async move {
+            let local = listener.local_addr()?;
+            log_info!("HTTPS proxy listening on https://{}", local);
 
-        tokio::spawn(async move {
-            // Get the current acceptor (allows hot-reload)
-            let tls_acceptor = acceptor.read().await.clone();
-            match tls_acceptor.accept(stream).await {
-                Ok(tls_stream) => {
-                    if let Err(e) = handle_tls_connection(tls_stream, registry).await {
-                        error!("HTTPS connection error: {}", e);
+            loop {
+                let (stream, addr) = listener.accept().await?;
+                let acceptor = acceptor.clone();
+                let registry = registry.clone();
+                let client = ClientInfo {
+                    addr,
+                    port: local.port(),
+                    tls: true,
+                };
+
+                tokio::spawn(async move {
+                    // Get the current acceptor (allows hot-reload)
+                    let tls_acceptor = acceptor.read().await.clone();
+                    match tls_acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            // Serve with the protocol negotiated through ALPN
+                            let is_h2 = tls_stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                            let result = if is_h2 {
+                                handle_h2_connection(tls_stream, registry, client).await
+                            } else {
+                                handle_connection(tls_stream, registry, client).await
+                            };
+                            if let Err(e) = result {
+                                error!("HTTPS connection error: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            error!("TLS handshake error: {}", e);
+                        }
                     }
-                }
-                Err(e) => {
-                    error!("TLS handshake error: {}", e);
-                }
+                });
             }
         });
     }
+    wait_for_servers(servers).await
+}
+
+/// Wait on listener tasks; the first one to stop takes the proxy down with it
+async fn wait_for_servers(mut servers: JoinSet<Result<()>>) -> Result<()> {
+    match servers.join_next().await {
+        Some(result) => result?,
+        None => Ok(()),
+    }
 }
 
-/// Handle a TLS connection
-async fn handle_tls_connection<S>(stream: S, registry: SharedRegistry) -> Result<()>
+/// Serve a client connection (plain TCP or TLS)
+///
+/// Speaks HTTP/1.1 or, when the client opens with the HTTP/2 preface, h2c.
+/// Every request is routed on its own host, so a keep-alive connection can
+/// reach several backends. Upgrades (WebSocket, etc.) are handled per request
+/// through hyper in handle_http_request.
+async fn handle_connection<S>(
+    stream: S,
+    registry: SharedRegistry,
+    client: ClientInfo,
+) -> Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    // For TLS connections, we use hyper directly (no WebSocket peek needed for now)
     let io = TokioIo::new(stream);
     let service = service_fn(move |req| {
         let registry = registry.clone();
-        async move { handle_http_request(req, registry).await }
+        async move { handle_http_request(req, registry, client).await }
     });
 
-    if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(io, service)
+        .await
+    {
+        // Don't log connection reset errors - they're normal
         if !e.to_string().contains("connection reset") {
-            error!("HTTPS proxy connection error: {}", e);
+            error!("Proxy connection error: {}", e);
         }
     }
 
     Ok(())
 }
 
-/// Handle a single connection - detect WebSocket upgrades vs regular HTTP
-async fn handle_connection(mut stream: TcpStream, registry: SharedRegistry) -> Result<()> {
-    // Peek at the first bytes to parse the HTTP request
-    let mut buf = vec![0u8; 4096];
-    let n = stream.peek(&mut buf).await?;
-    let peek_data = &buf[..n];
+/// Serve an HTTP/2 client connection (negotiated via ALPN on the TLS listener)
+async fn handle_h2_connection<S>(
+    stream: S,
+    registry: SharedRegistry,
+    client: ClientInfo,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = TokioIo::new(stream);
+    let service = service_fn(move |req| {
+        let registry = registry.clone();
+        async move { handle_http_request(req, registry, client).await }
+    });
 
-    // Parse headers to check for WebSocket upgrade
-    let header_str = String::from_utf8_lossy(peek_data);
-    let is_websocket =
-        header_str.contains("Upgrade: websocket") || header_str.contains("upgrade: websocket");
+    if let Err(e) = http2::Builder::new(TokioExecutor::new())
+        .serve_connection(io, service)
+        .await
+    {
+        if !e.to_string().contains("connection reset") {
+            error!("HTTP/2 proxy connection error: {}", e);
+        }
+    }
 
-    // Extract host from headers
-    let host = extract_host_from_headers(&header_str).unwrap_or_default();
-    let domain = host.split(':').next().unwrap_or(&host).to_string();
+    Ok(())
+}
 
-    // Look up the service
-    let port = {
-        let reg = registry.read().await;
-        reg.get(&domain).map(|s| s.port)
-    };
+/// Details of the client connection a request arrived on
+#[derive(Debug, Clone, Copy)]
+struct ClientInfo {
+    /// Client address
+    addr: SocketAddr,
+    /// Proxy port the client connected to
+    port: u16,
+    /// Whether the connection is TLS
+    tls: bool,
+}
 
-    if is_websocket {
-        // WebSocket: tunnel raw TCP
-        if let Some(port) = port {
-            handle_websocket_tunnel(stream, port).await?;
+impl ClientInfo {
+    fn proto(&self) -> &'static str {
+        if self.tls {
+            "https"
         } else {
-            // No service found - send 404 and close
-            let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
-            stream.write_all(response.as_bytes()).await?;
+            "http"
         }
+    }
+}
+
+/// Add X-Forwarded-* and RFC 7239 Forwarded headers describing the original request
+///
+/// For and Forwarded are appended to any values set by proxies in front of us;
+/// the others describe this hop and replace whatever the client sent.
+fn add_forwarded_headers<B>(req: &mut Request<B>, client: &ClientInfo) {
+    let host = request_host(req);
+    let ip = client.addr.ip();
+    let proto = client.proto();
+
+    // IPv6 addresses must be bracketed and quoted in Forwarded
+    let node = if ip.is_ipv6() {
+        format!("\"[{}]\"", ip)
     } else {
-        // Regular HTTP: use hyper
-        let io = TokioIo::new(stream);
-        let service = service_fn(move |req| {
-            let registry = registry.clone();
-            async move { handle_http_request(req, registry).await }
-        });
+        ip.to_string()
+    };
+    let mut forwarded = format!("for={};proto={}", node, proto);
+    if !host.is_empty() {
+        forwarded.push_str(&format!(";host=\"{}\"", host));
+    }
 
-        if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
-            // Don't log connection reset errors - they're normal
-            if !e.to_string().contains("connection reset") {
-                error!("Proxy connection error: {}", e);
-            }
+    let headers = req.headers_mut();
+    append_header_value(headers, "x-forwarded-for", &ip.to_string());
+    append_header_value(headers, "forwarded", &forwarded);
+
+    let mut set = |name: &'static str, value: &str| {
+        if let Ok(value) = header::HeaderValue::from_str(value) {
+            headers.insert(name, value);
         }
+    };
+    set("x-forwarded-proto", proto);
+    set("x-forwarded-port", &client.port.to_string());
+    if !host.is_empty() {
+        set("x-forwarded-host", &host);
     }
+}
 
-    Ok(())
+/// Append to a comma-separated header, joining any existing values into one
+fn append_header_value(headers: &mut header::HeaderMap, name: &'static str, value: &str) {
+    let mut values: Vec<String> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(String::from)
+        .collect();
+    values.push(value.to_string());
+
+    if let Ok(value) = header::HeaderValue::from_str(&values.join(", ")) {
+        headers.insert(name, value);
+    }
 }
 
-/// Extract Host header from raw HTTP headers
-fn extract_host_from_headers(headers: &str) -> Option<String> {
-    for line in headers.lines() {
-        let lower = line.to_lowercase();
-        if lower.starts_with("host:") {
-            return Some(line[5..].trim().to_string());
+/// Remove a route prefix from the request path, keeping the query string
+fn strip_path_prefix<B>(req: &mut Request<B>, prefix: &str) {
+    let path = req.uri().path();
+    let rest = path.strip_prefix(prefix).unwrap_or(path);
+    let mut stripped = if rest.starts_with('/') {
+        rest.to_string()
+    } else {
+        format!("/{}", rest)
+    };
+    if let Some(query) = req.uri().query() {
+        stripped.push('?');
+        stripped.push_str(query);
+    }
+
+    let mut parts = req.uri().clone().into_parts();
+    if let Ok(path_and_query) = stripped.parse() {
+        parts.path_and_query = Some(path_and_query);
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *req.uri_mut() = uri;
         }
     }
-    None
 }
 
-/// Handle WebSocket upgrade by tunneling raw TCP
-async fn handle_websocket_tunnel(mut client: TcpStream, backend_port: u16) -> Result<()> {
-    use tokio::io::copy_bidirectional;
+/// Get the requested host: the Host header (HTTP/1.1) or the URI authority (HTTP/2)
+fn request_host<B>(req: &Request<B>) -> String {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()))
+        .unwrap_or("")
+        .to_string()
+}
 
-    // Connect to backend
-    let mut backend = match TcpStream::connect(format!("127.0.0.1:{}", backend_port)).await {
-        Ok(s) => s,
-        Err(e) => {
-            warn!("Failed to connect to backend for WebSocket: {}", e);
-            return Ok(());
-        }
-    };
+/// Header the dashboard sends its token in
+const DASHBOARD_TOKEN_HEADER: &str = "x-unport-token";
 
-    // Tunnel all data bidirectionally (including the initial HTTP upgrade request)
-    match copy_bidirectional(&mut client, &mut backend).await {
-        Ok((client_to_backend, backend_to_client)) => {
-            log_info!(
-                "WebSocket tunnel closed: {} bytes up, {} bytes down",
-                client_to_backend, backend_to_client
-            );
-        }
-        Err(e) => {
-            // Connection reset is normal when WebSocket closes
-            if !e.to_string().contains("reset") {
-                warn!("WebSocket tunnel error: {}", e);
-            }
-        }
+/// Secret embedded in the dashboard page, required for its mutating actions
+///
+/// 16 bytes from /dev/urandom, generated once per daemon, so other sites
+/// can't learn or guess it.
+fn dashboard_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        let mut bytes = [0u8; 16];
+        std::fs::File::open("/dev/urandom")
+            .and_then(|mut random| random.read_exact(&mut bytes))
+            .expect("Failed to read /dev/urandom");
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    })
+}
+
+/// Check that a dashboard action comes from the dashboard itself
+///
+/// Requires POST (so links and images can't trigger it), the per-daemon token
+/// from the page, and an Origin matching the dashboard's own when one is sent.
+fn check_dashboard_action<B>(
+    req: &Request<B>,
+    host: &str,
+    client: ClientInfo,
+) -> Result<(), &'static str> {
+    if req.method() != hyper::Method::POST {
+        return Err("method not allowed");
     }
+    check_dashboard_request(req, host, client)
+}
 
+/// Check the token and Origin of a request made by the dashboard page
+fn check_dashboard_request<B>(
+    req: &Request<B>,
+    host: &str,
+    client: ClientInfo,
+) -> Result<(), &'static str> {
+    let token = req
+        .headers()
+        .get(DASHBOARD_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    if token != Some(dashboard_token()) {
+        return Err("invalid token");
+    }
+
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let expected = format!("{}://{}", client.proto(), host);
+        if origin.to_str().ok() != Some(expected.as_str()) {
+            return Err("cross-origin request");
+        }
+    }
     Ok(())
 }
 
 /// Handle regular HTTP request
+/// Host of a `Host` header without its port, keeping IPv6 brackets
+/// ("[::1]:8080" becomes "[::1]")
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if port.bytes().all(|b| b.is_ascii_digit())
+                && (name.ends_with(']') || !name.contains(':')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+/// Hosts the dashboard answers on: loopback by name or address
+fn is_dashboard_host(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]" | "::1")
+}
+
 async fn handle_http_request(
-    req: Request<Incoming>,
+    mut req: Request<Incoming>,
     registry: SharedRegistry,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    client: ClientInfo,
+) -> Result<Response<ProxyBody>, hyper::Error> {
     // Extract host from request
-    let host = req
-        .headers()
-        .get("host")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("")
-        .to_string();
+    let host = request_host(&req);
 
     // Remove port from host if present
-    let domain = host.split(':').next().unwrap_or(&host).to_string();
+    let domain = strip_port(&host).to_string();
 
-    // Look up the service
-    let port = {
+    // Look up the service (following path routes)
+    let (route, pool, start_deadline) = {
         let reg = registry.read().await;
-        reg.get(&domain).map(|s| s.port)
+        let route = reg.resolve(&domain, req.uri().path());
+        let start_deadline = match &route {
+            Some(Ok(route)) => reg.start_deadline(&route.service.domain),
+            _ => None,
+        };
+        (route, reg.pool(), start_deadline)
     };
 
-    match port {
-        Some(port) => {
+    match route {
+        Some(Err(target)) => Ok(Response::builder()
+            .status(502)
+            .header("content-type", "text/plain")
+            .body(full(format!(
+                "unport: Route target '{}' for {}{} is not running.",
+                target,
+                domain,
+                req.uri().path()
+            )))
+            .unwrap()),
+        Some(Ok(Route {
+            service,
+            strip_prefix,
+        })) => {
+            if service.options.forwarded_headers {
+                add_forwarded_headers(&mut req, &client);
+            }
+            if let Some(prefix) = strip_prefix {
+                strip_path_prefix(&mut req, &prefix);
+                if service.options.forwarded_headers {
+                    if let Ok(value) = header::HeaderValue::from_str(&prefix) {
+                        req.headers_mut().insert("x-forwarded-prefix", value);
+                    }
+                }
+            }
+
+            // A service that was just started may not be listening yet
+            if let Some(deadline) = start_deadline {
+                let h2 = use_h2c(&req, service.options.upstream);
+                if !pool.wait_until_listening(service.port, deadline, h2).await {
+                    return Ok(starting_response(&registry, service).await);
+                }
+            }
+
             // Forward the request to the backend
-            match forward_request(req, port).await {
+            let result = if is_upgrade_request(&req) {
+                forward_upgrade(req, service.port, &pool).await
+            } else {
+                forward_request(req, &service, &pool).await
+            };
+            match result {
                 Ok(response) => Ok(response),
+                Err(e) if is_connect_error(&e) => Ok(starting_response(&registry, service).await),
                 Err(e) => {
                     warn!("Failed to forward request to {}: {}", domain, e);
                     Ok(Response::builder()
                         .status(502)
-                        .body(Full::new(Bytes::from(format!("Bad Gateway: {}", e))))
+                        .body(full(format!("Bad Gateway: {}", e)))
                         .unwrap())
                 }
             }
         }
         None => {
             // Show dashboard for localhost, 404 for unknown domains
-            if domain == "localhost" || domain == "127.0.0.1" {
+            if is_dashboard_host(&domain) {
                 let path = req.uri().path();
 
                 // Handle kill API endpoint
                 if path.starts_with("/api/kill/") {
+                    if let Err(reason) = check_dashboard_action(&req, &host, client) {
+                        warn!("Refused dashboard action {} {}: {}", req.method(), path, reason);
+                        return Ok(Response::builder()
+                            .status(403)
+                            .header("content-type", "application/json")
+                            .body(full(format!(r#"{{"error":"{}"}}"#, reason)))
+                            .unwrap());
+                    }
                     let target_domain = path.strip_prefix("/api/kill/").unwrap_or("");
                     if !target_domain.is_empty() {
-                        let mut reg = registry.write().await;
-                        if let Some(service) = reg.unregister(target_domain) {
-                            unsafe {
-                                libc::kill(service.pid as i32, libc::SIGTERM);
-                            }
+                        let service = registry.write().await.unregister(target_domain);
+                        if let Some(service) = service {
+                            stop_service(&service).await;
                             log_info!("Killed service: {}", target_domain);
                             return Ok(Response::builder()
                                 .status(200)
                                 .header("content-type", "application/json")
-                                .body(Full::new(Bytes::from(r#"{"ok":true}"#)))
+                                .body(full(r#"{"ok":true}"#))
                                 .unwrap());
                         } else {
                             return Ok(Response::builder()
                                 .status(404)
                                 .header("content-type", "application/json")
-                                .body(Full::new(Bytes::from(r#"{"error":"not found"}"#)))
+                                .body(full(r#"{"error":"not found"}"#))
                                 .unwrap());
                         }
                     }
                 }
 
+                // Handle log viewer endpoint
+                if let Some(target_domain) = path.strip_prefix("/api/logs/") {
+                    if let Err(reason) = check_dashboard_request(&req, &host, client) {
+                        warn!("Refused dashboard logs {}: {}", path, reason);
+                        return Ok(json_response(403, format!(r#"{{"error":"{}"}}"#, reason)));
+                    }
+                    let reg = registry.read().await;
+                    return Ok(match reg.logs().lines(target_domain, None) {
+                        Some((lines, _)) => {
+                            json_response(200, serde_json::to_string(&lines).unwrap_or_default())
+                        }
+                        None if reg.get(target_domain).is_some() => json_response(200, "[]"),
+                        None => json_response(404, r#"{"error":"not found"}"#),
+                    });
+                }
+
                 let reg = registry.read().await;
                 let services = reg.list();
-                let html = render_dashboard(&services);
+                let html = render_dashboard(&services, client.proto(), client.port);
                 Ok(Response::builder()
                     .status(200)
                     .header("content-type", "text/html; charset=utf-8")
-                    .body(Full::new(Bytes::from(html)))
+                    .body(full(html))
                     .unwrap())
             } else {
                 let reg = registry.read().await;
                 let services = reg.list();
                 let available = services
                     .iter()
-                    .map(|s| format!("  - http://{}", s.domain))
+                    .map(|s| format!("  - {}", service_url(client.proto(), &s.domain, client.port)))
                     .collect::<Vec<_>>()
                     .join("\n");
 
@@ -283,74 +543,295 @@ async fn handle_http_request(
                 Ok(Response::builder()
                     .status(404)
                     .header("content-type", "text/plain")
-                    .body(Full::new(Bytes::from(body)))
+                    .body(full(body))
                     .unwrap())
             }
         }
     }
 }
 
-async fn forward_request(req: Request<Incoming>, port: u16) -> Result<Response<Full<Bytes>>> {
-    // Try localhost (which resolves to IPv4 or IPv6) first, then fallback to 127.0.0.1
-    let stream = match TcpStream::connect(format!("localhost:{}", port)).await {
-        Ok(s) => s,
-        Err(_) => TcpStream::connect(format!("127.0.0.1:{}", port)).await?,
+fn json_response(status: u16, body: impl Into<Bytes>) -> Response<ProxyBody> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(full(body))
+        .unwrap()
+}
+
+/// Whether forwarding failed because nothing accepted the connection
+///
+/// Only a refused connect counts. A reset or broken pipe once the request is
+/// under way is the app failing, not an app that isn't listening yet.
+fn is_connect_error(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused)
+}
+
+/// Page shown while a service isn't accepting connections
+///
+/// Re-reads the service so the page shows its latest status, e.g. after a
+/// crash while the request was waiting.
+async fn starting_response(registry: &SharedRegistry, service: Service) -> Response<ProxyBody> {
+    let service = registry
+        .read()
+        .await
+        .get(&service.domain)
+        .cloned()
+        .unwrap_or(service);
+    Response::builder()
+        .status(503)
+        .header("content-type", "text/html; charset=utf-8")
+        .header("cache-control", "no-store")
+        .header("retry-after", STARTING_REFRESH_SECS.to_string())
+        .body(full(render_starting_page(&service)))
+        .unwrap()
+}
+
+/// Forward a request to the backend, streaming both bodies
+///
+/// Neither body is buffered: request frames are pulled from the client as the
+/// backend connection accepts them, and the response body is handed back to
+/// hyper as-is, so chunked encoding, trailers and backpressure carry through.
+/// Backend connections come from the keep-alive pool.
+async fn forward_request(
+    req: Request<Incoming>,
+    service: &Service,
+    pool: &Arc<BackendPool>,
+) -> Result<Response<ProxyBody>> {
+    let response = if use_h2c(&req, service.options.upstream) {
+        pool.send_h2(service.port, to_h2_request(req)).await?
+    } else {
+        pool.send_http1(service.port, to_http1_request(req)).await?
     };
-    let io = TokioIo::new(stream);
+    Ok(response.map(|body| body.boxed()))
+}
+
+/// Decide whether a request goes to the backend over cleartext HTTP/2
+fn use_h2c<B>(req: &Request<B>, upstream: UpstreamProtocol) -> bool {
+    match upstream {
+        UpstreamProtocol::Http1 => false,
+        UpstreamProtocol::H2c => true,
+        UpstreamProtocol::Auto => is_grpc_request(req),
+    }
+}
+
+/// gRPC always runs over HTTP/2 and is identified by its content type
+fn is_grpc_request<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/grpc"))
+}
+
+/// Rewrite a request so it can be sent over an HTTP/2 backend connection
+///
+/// HTTP/2 needs a scheme and authority on the URI; for HTTP/1.1 clients the
+/// authority comes from the Host header, which is then dropped in favour of it.
+fn to_h2_request<B>(mut req: Request<B>) -> Request<B> {
+    let authority = request_host(&req);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    if let Ok(uri) = format!("http://{}{}", authority, path).parse::<Uri>() {
+        *req.uri_mut() = uri;
+    }
+    req.headers_mut().remove(header::HOST);
+    *req.version_mut() = Version::HTTP_2;
+
+    req
+}
+
+/// Rewrite an HTTP/2 request so it can be sent over an HTTP/1.1 backend connection
+///
+/// HTTP/2 carries the host in the `:authority` pseudo-header, uses absolute-form
+/// URIs and may split cookies across several headers; HTTP/1.1 backends expect a
+/// Host header, an origin-form path and a single Cookie header.
+fn to_http1_request<B>(mut req: Request<B>) -> Request<B> {
+    if req.version() != Version::HTTP_2 {
+        return req;
+    }
+
+    if !req.headers().contains_key(header::HOST) {
+        if let Some(authority) = req.uri().authority() {
+            if let Ok(value) = header::HeaderValue::from_str(authority.as_str()) {
+                req.headers_mut().insert(header::HOST, value);
+            }
+        }
+    }
+
+    let cookies: Vec<String> = req
+        .headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(String::from)
+        .collect();
+    if cookies.len() > 1 {
+        if let Ok(value) = header::HeaderValue::from_str(&cookies.join("; ")) {
+            req.headers_mut().insert(header::COOKIE, value);
+        }
+    }
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    if let Ok(uri) = path.parse::<Uri>() {
+        *req.uri_mut() = uri;
+    }
+    *req.version_mut() = Version::HTTP_11;
+
+    req
+}
+
+/// Check whether a request asks to switch protocols (`Connection: upgrade` + `Upgrade`)
+fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    let connection_upgrade = req
+        .headers()
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    connection_upgrade && req.headers().contains_key(header::UPGRADE)
+}
+
+/// Forward an upgrade request and, if the backend agrees, tunnel the upgraded streams
+async fn forward_upgrade(
+    mut req: Request<Incoming>,
+    port: u16,
+    pool: &Arc<BackendPool>,
+) -> Result<Response<ProxyBody>> {
+    let client_upgrade = hyper::upgrade::on(&mut req);
+    let mut response = pool
+        .send_http1(port, to_http1_request(req))
+        .await?
+        .map(|body| body.boxed());
 
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+    // Backend declined the upgrade - relay its response as a normal one
+    if response.status() != hyper::StatusCode::SWITCHING_PROTOCOLS {
+        return Ok(response);
+    }
 
+    let backend_upgrade = hyper::upgrade::on(&mut response);
     tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            error!("Connection error: {}", e);
+        match tokio::try_join!(client_upgrade, backend_upgrade) {
+            Ok((client, backend)) => tunnel_upgraded(client, backend).await,
+            Err(e) => warn!("Upgrade failed: {}", e),
         }
     });
 
-    let (parts, body) = req.into_parts();
-    let body_bytes = body.collect().await?.to_bytes();
-    let new_req = Request::from_parts(parts, Full::new(body_bytes));
+    Ok(response)
+}
 
-    let response = sender.send_request(new_req).await?;
-    let (parts, body) = response.into_parts();
-    let body_bytes = body.collect().await?.to_bytes();
+/// Copy bytes both ways between an upgraded client and backend connection
+async fn tunnel_upgraded(client: hyper::upgrade::Upgraded, backend: hyper::upgrade::Upgraded) {
+    let mut client = TokioIo::new(client);
+    let mut backend = TokioIo::new(backend);
 
-    Ok(Response::from_parts(parts, Full::new(body_bytes)))
+    match tokio::io::copy_bidirectional(&mut client, &mut backend).await {
+        Ok((client_to_backend, backend_to_client)) => {
+            log_info!(
+                "Upgraded tunnel closed: {} bytes up, {} bytes down",
+                client_to_backend, backend_to_client
+            );
+        }
+        Err(e) => {
+            // Connection reset is normal when WebSocket closes
+            if !e.to_string().contains("reset") {
+                warn!("Upgraded tunnel error: {}", e);
+            }
+        }
+    }
+}
+
+/// Escape text for HTML element content and quoted attribute values
+///
+/// `>` is left alone: it can't open a tag or end a quoted attribute.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
-fn is_process_alive(pid: u32) -> bool {
-    unsafe { libc::kill(pid as i32, 0) == 0 }
+/// Escape text for a single-quoted JavaScript string in an inline event handler
+fn escape_js(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
-fn render_dashboard(services: &[Service]) -> String {
+fn render_dashboard(services: &[Service], scheme: &str, port: u16) -> String {
     let service_rows = if services.is_empty() {
         r#"<tr><td colspan="4" class="empty">No services running. Start one with <code>unport start</code></td></tr>"#.to_string()
     } else {
         services
             .iter()
             .map(|s| {
-                let url = format!("http://{}", s.domain);
-                let status = if is_process_alive(s.pid) {
-                    "running"
-                } else {
-                    "stopped"
-                };
-                let status_class = if is_process_alive(s.pid) {
-                    "status-running"
-                } else {
-                    "status-stopped"
-                };
+                let url = service_url(scheme, &s.domain, port);
+                let domain = escape_html(&s.domain);
+                let status = s.status(process::is_service_alive(s));
+                let status_class = format!("status-{}", status);
+                let wildcard = s
+                    .options
+                    .wildcard
+                    .then(|| format!(r#"<div class="route">*.{}</div>"#, domain));
+                let routes = wildcard
+                    .into_iter()
+                    .chain(
+                        s.options
+                            .routes
+                            .iter()
+                            .map(|r| format!(r#"<div class="route">{}</div>"#, escape_html(&r.to_string()))),
+                    )
+                    .collect::<String>();
+                let exits = s
+                    .exit_summary()
+                    .map(|summary| format!(r#"<div class="exits">{}</div>"#, escape_html(&summary)))
+                    .unwrap_or_default();
                 format!(
                     r#"<tr id="row-{}">
-                        <td><span class="status-dot {}"></span>{}</td>
-                        <td class="url">{}</td>
+                        <td><span class="status-dot {}"></span>{}{}</td>
+                        <td class="url">{}{}</td>
                         <td>{}</td>
                         <td class="actions">
                             <button class="btn btn-copy" onclick="copyToClipboard('{}')">Copy</button>
                             <a href="{}" class="btn btn-go" target="_blank">Open</a>
+                            <button class="btn btn-copy" onclick="showLogs('{}')">Logs</button>
                             <button class="btn btn-kill" onclick="killService('{}')">Kill</button>
                         </td>
                     </tr>"#,
-                    s.domain, status_class, status, url, s.port, url, url, s.domain
+                    domain,
+                    status_class,
+                    escape_html(status),
+                    exits,
+                    escape_html(&url),
+                    routes,
+                    s.port,
+                    escape_html(&escape_js(&url)),
+                    escape_html(&url),
+                    escape_html(&escape_js(&s.domain)),
+                    escape_html(&escape_js(&s.domain))
                 )
             })
             .collect::<Vec<_>>()
@@ -363,6 +844,7 @@ fn render_dashboard(services: &[Service]) -> String {
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="unport-token" content="{}">
     <title>unport - Local Development Services</title>
     <style>
         * {{
@@ -426,6 +908,16 @@ fn render_dashboard(services: &[Service]) -> String {
             font-family: 'SF Mono', Monaco, 'Courier New', monospace;
             color: #3b82f6;
         }}
+        .route {{
+            color: #666;
+            font-size: 12px;
+            margin-top: 4px;
+        }}
+        .exits {{
+            color: #b45309;
+            font-size: 12px;
+            margin-top: 4px;
+        }}
         .status-dot {{
             display: inline-block;
             width: 8px;
@@ -433,11 +925,14 @@ fn render_dashboard(services: &[Service]) -> String {
             border-radius: 50%;
             margin-right: 8px;
         }}
-        .status-running {{
+        .status-running, .status-healthy {{
             background: #22c55e;
             box-shadow: 0 0 8px rgba(34, 197, 94, 0.5);
         }}
-        .status-stopped {{
+        .status-starting {{
+            background: #f59e0b;
+        }}
+        .status-stopped, .status-unhealthy {{
             background: #ef4444;
         }}
         .actions {{
@@ -489,6 +984,34 @@ fn render_dashboard(services: &[Service]) -> String {
             font-family: 'SF Mono', Monaco, 'Courier New', monospace;
             font-size: 13px;
         }}
+        .logs {{
+            margin-top: 24px;
+            background: #141414;
+            border-radius: 8px;
+            overflow: hidden;
+        }}
+        .logs-header {{
+            display: flex;
+            justify-content: space-between;
+            align-items: center;
+            padding: 12px 16px;
+            border-bottom: 1px solid #222;
+            font-size: 14px;
+        }}
+        .logs pre {{
+            margin: 0;
+            padding: 16px;
+            max-height: 400px;
+            overflow: auto;
+            font-family: 'SF Mono', Monaco, 'Courier New', monospace;
+            font-size: 12px;
+            line-height: 1.5;
+            white-space: pre-wrap;
+            word-break: break-all;
+        }}
+        .logs .stderr {{
+            color: #f87171;
+        }}
         .toast {{
             position: fixed;
             bottom: 20px;
@@ -528,6 +1051,13 @@ fn render_dashboard(services: &[Service]) -> String {
                 {}
             </tbody>
         </table>
+        <div class="logs" id="logs" hidden>
+            <div class="logs-header">
+                <span id="logs-title"></span>
+                <button class="btn btn-copy" onclick="hideLogs()">Close</button>
+            </div>
+            <pre id="logs-output"></pre>
+        </div>
     </div>
     <div class="toast" id="toast">Copied to clipboard</div>
     <script>
@@ -538,7 +1068,11 @@ fn render_dashboard(services: &[Service]) -> String {
         }}
         function killService(domain) {{
             if (confirm('Kill ' + domain + '?')) {{
-                fetch('/api/kill/' + domain, {{ method: 'POST' }})
+                const token = document.querySelector('meta[name="unport-token"]').content;
+                fetch('/api/kill/' + domain, {{
+                    method: 'POST',
+                    headers: {{ 'X-Unport-Token': token }}
+                }})
                     .then(r => r.json())
                     .then(data => {{
                         if (data.ok) {{
@@ -552,6 +1086,37 @@ fn render_dashboard(services: &[Service]) -> String {
                     .catch(() => showToast('Failed to kill service'));
             }}
         }}
+        let logsTimer = null;
+        function showLogs(domain) {{
+            const token = document.querySelector('meta[name="unport-token"]').content;
+            const output = document.getElementById('logs-output');
+            document.getElementById('logs-title').textContent = domain;
+            document.getElementById('logs').hidden = false;
+            output.textContent = '';
+            const load = () => fetch('/api/logs/' + domain, {{
+                headers: {{ 'X-Unport-Token': token }}
+            }})
+                .then(r => r.json())
+                .then(lines => {{
+                    const atBottom = output.scrollTop + output.clientHeight >= output.scrollHeight - 4;
+                    output.replaceChildren(...(Array.isArray(lines) ? lines : []).map(line => {{
+                        const span = document.createElement('span');
+                        span.className = line.stream;
+                        span.textContent = line.text + '\n';
+                        return span;
+                    }}));
+                    if (!Array.isArray(lines)) output.textContent = lines.error || 'No logs';
+                    if (atBottom) output.scrollTop = output.scrollHeight;
+                }})
+                .catch(() => {{}});
+            clearInterval(logsTimer);
+            load();
+            logsTimer = setInterval(load, 2000);
+        }}
+        function hideLogs() {{
+            clearInterval(logsTimer);
+            document.getElementById('logs').hidden = true;
+        }}
         function showToast(msg) {{
             const toast = document.getElementById('toast');
             toast.textContent = msg;
@@ -561,65 +1126,115 @@ fn render_dashboard(services: &[Service]) -> String {
     </script>
 </body>
 </html>"##,
+        dashboard_token(),
         service_rows
     )
 }
 
+/// How often the "starting" page reloads itself
+const STARTING_REFRESH_SECS: u64 = 2;
+
+fn render_starting_page(service: &Service) -> String {
+    let alive = process::is_service_alive(service);
+    let status = service.status(alive);
+    let message = if alive {
+        format!("Waiting for it to listen on port {}.", service.port)
+    } else {
+        "The process isn't running.".to_string()
+    };
+    let exits = service
+        .exit_summary()
+        .map(|summary| format!(r#"<p class="exits">{}</p>"#, escape_html(&summary)))
+        .unwrap_or_default();
+
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta http-equiv="refresh" content="{refresh}">
+    <title>{domain} - unport</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, sans-serif;
+            background: #0a0a0a;
+            color: #e5e5e5;
+            min-height: 100vh;
+            margin: 0;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+        }}
+        .card {{
+            background: #141414;
+            border-radius: 8px;
+            padding: 32px 40px;
+            max-width: 480px;
+        }}
+        h1 {{
+            font-size: 20px;
+            font-weight: 600;
+            color: #fff;
+            margin: 0 0 16px;
+        }}
+        p {{
+            color: #999;
+            font-size: 14px;
+            margin: 8px 0;
+        }}
+        .exits {{
+            color: #b45309;
+        }}
+        .status-dot {{
+            display: inline-block;
+            width: 8px;
+            height: 8px;
+            border-radius: 50%;
+            margin-right: 8px;
+        }}
+        .status-running, .status-healthy {{
+            background: #22c55e;
+        }}
+        .status-starting {{
+            background: #f59e0b;
+        }}
+        .status-stopped, .status-unhealthy {{
+            background: #ef4444;
+        }}
+    </style>
+</head>
+<body>
+    <div class="card">
+        <h1>{domain}</h1>
+        <p><span class="status-dot status-{status}"></span>{status} (PID {pid})</p>
+        <p>{message}</p>
+        {exits}
+        <p>This page reloads every {refresh} seconds.</p>
+    </div>
+</body>
+</html>"##,
+        refresh = STARTING_REFRESH_SECS,
+        domain = escape_html(&service.domain),
+        status = status,
+        pid = service.pid,
+        message = message,
+        exits = exits,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{Health, RouteRule, ServiceOptions};
     use std::path::PathBuf;
-
-    #[test]
-    fn test_extract_host_from_headers_simple() {
-        let headers = "GET / HTTP/1.1\r\nHost: api.localhost\r\n";
-        let host = extract_host_from_headers(headers);
-        assert_eq!(host, Some("api.localhost".to_string()));
-    }
-
-    #[test]
-    fn test_extract_host_from_headers_with_port() {
-        let headers = "GET / HTTP/1.1\r\nHost: api.localhost:80\r\n";
-        let host = extract_host_from_headers(headers);
-        assert_eq!(host, Some("api.localhost:80".to_string()));
-    }
-
-    #[test]
-    fn test_extract_host_from_headers_lowercase() {
-        let headers = "GET / HTTP/1.1\r\nhost: web.localhost\r\n";
-        let host = extract_host_from_headers(headers);
-        assert_eq!(host, Some("web.localhost".to_string()));
-    }
-
-    #[test]
-    fn test_extract_host_from_headers_not_found() {
-        let headers = "GET / HTTP/1.1\r\nConnection: keep-alive\r\n";
-        let host = extract_host_from_headers(headers);
-        assert!(host.is_none());
-    }
-
-    #[test]
-    fn test_extract_host_with_whitespace() {
-        let headers = "GET / HTTP/1.1\r\nHost:   spaces.localhost   \r\n";
-        let host = extract_host_from_headers(headers);
-        assert_eq!(host, Some("spaces.localhost".to_string()));
-    }
-
-    #[test]
-    fn test_is_process_alive_current() {
-        let pid = std::process::id();
-        assert!(is_process_alive(pid));
-    }
-
-    #[test]
-    fn test_is_process_alive_dead() {
-        assert!(!is_process_alive(4000000));
-    }
+    use tokio::net::TcpStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     #[test]
     fn test_render_dashboard_empty_services() {
         let services: Vec<Service> = vec![];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("<!DOCTYPE html>"));
         assert!(html.contains("No services running"));
         assert!(html.contains("unport start"));
@@ -633,9 +1248,10 @@ mod tests {
                 port: 4000,
                 pid: std::process::id(), // Use current PID so it's "alive"
                 directory: PathBuf::from("/app/api"),
+                ..Default::default()
             },
         ];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("<!DOCTYPE html>"));
         assert!(html.contains("http://api.localhost"));
         assert!(html.contains("4000"));
@@ -644,7 +1260,7 @@ mod tests {
     #[test]
     fn test_render_dashboard_contains_required_elements() {
         let services: Vec<Service> = vec![];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("<title>"));
         assert!(html.contains("unport"));
         assert!(html.contains("<table>"));
@@ -659,12 +1275,72 @@ mod tests {
             port: 4000,
             pid: 4000000, // Non-existent PID
             directory: PathBuf::from("/app/dead"),
+            ..Default::default()
         }];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("stopped"));
         assert!(html.contains("status-stopped"));
     }
 
+    #[test]
+    fn test_render_dashboard_has_log_viewer() {
+        let services = vec![Service {
+            domain: "test.localhost".to_string(),
+            port: 4001,
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/test"),
+            ..Default::default()
+        }];
+        let html = render_dashboard(&services, "http", 80);
+        assert!(html.contains("showLogs('test.localhost')"));
+        assert!(html.contains(r#"id="logs-output""#));
+    }
+
+    #[test]
+    fn test_render_dashboard_escapes_values() {
+        let services = vec![Service {
+            domain: "<img src=x>'.localhost".to_string(),
+            port: 4000,
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/evil"),
+            options: ServiceOptions {
+                wildcard: true,
+                routes: vec![RouteRule {
+                    path: "/<script>".to_string(),
+                    service: "\"api\"".to_string(),
+                    strip_prefix: false,
+                }],
+                ..Default::default()
+            },
+            last_exit: Some("<b>signal</b>".to_string()),
+            ..Default::default()
+        }];
+        let html = render_dashboard(&services, "http", 80);
+        assert!(!html.contains("<img"));
+        assert!(!html.contains("/<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("*.&lt;img src=x>&#39;.localhost"));
+        assert!(html.contains("/&lt;script> -> &quot;api&quot;"));
+        assert!(html.contains("last exit: &lt;b>signal&lt;/b>"));
+        // Quotes can't end the string argument of the inline handlers
+        assert!(html.contains("showLogs('&lt;img src=x>\\&#39;.localhost')"));
+    }
+
+    #[test]
+    fn test_render_dashboard_shows_health() {
+        let services = vec![Service {
+            domain: "checked.localhost".to_string(),
+            port: 4000,
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/checked"),
+            health: Some(Health::Unhealthy),
+            ..Default::default()
+        }];
+        let html = render_dashboard(&services, "http", 80);
+        assert!(html.contains("status-unhealthy"));
+        assert!(html.contains("unhealthy</td>"));
+    }
+
     #[test]
     fn test_render_dashboard_has_actions() {
         let services = vec![Service {
@@ -672,8 +1348,9 @@ mod tests {
             port: 4001,
             pid: std::process::id(),
             directory: PathBuf::from("/app/test"),
+            ..Default::default()
         }];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("Copy"));
         assert!(html.contains("Open"));
         assert!(html.contains("Kill"));
@@ -687,18 +1364,929 @@ mod tests {
                 port: 4000,
                 pid: std::process::id(),
                 directory: PathBuf::from("/app/api"),
+                ..Default::default()
             },
             Service {
                 domain: "web.localhost".to_string(),
                 port: 4001,
                 pid: std::process::id(),
                 directory: PathBuf::from("/app/web"),
+                ..Default::default()
             },
         ];
-        let html = render_dashboard(&services);
+        let html = render_dashboard(&services, "http", 80);
         assert!(html.contains("http://api.localhost"));
         assert!(html.contains("http://web.localhost"));
         assert!(html.contains("4000"));
         assert!(html.contains("4001"));
     }
+
+    fn test_client(addr: SocketAddr) -> ClientInfo {
+        ClientInfo {
+            addr,
+            port: 80,
+            tls: false,
+        }
+    }
+
+    fn test_registry(domain: &str, port: u16) -> SharedRegistry {
+        let mut registry = Registry::new();
+        registry.register(Service {
+            domain: domain.to_string(),
+            port,
+            pid: std::process::id(),
+            directory: PathBuf::from("/test"),
+            ..Default::default()
+        });
+        Arc::new(RwLock::new(registry))
+    }
+
+    /// Spawn a proxy listener that serves a single connection for `domain -> port`
+    async fn spawn_test_proxy(domain: &str, port: u16) -> SocketAddr {
+        let registry = test_registry(domain, port);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let _ = handle_connection(stream, registry, test_client(peer)).await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_response_body_is_streamed() {
+        // Backend sends one chunk, then waits before finishing the response
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nfirst\r\n")
+                .await
+                .unwrap();
+            let _ = release_rx.await;
+            stream.write_all(b"4\r\nlast\r\n0\r\n\r\n").await.unwrap();
+        });
+
+        let proxy = spawn_test_proxy("stream.localhost", backend_port).await;
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(b"GET /events HTTP/1.1\r\nHost: stream.localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        // The first chunk must arrive while the backend is still holding the rest
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !String::from_utf8_lossy(&received).contains("first") {
+            let n = tokio::time::timeout(std::time::Duration::from_secs(5), client.read(&mut buf))
+                .await
+                .expect("first chunk was not streamed")
+                .unwrap();
+            assert!(n > 0, "connection closed early");
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert!(!String::from_utf8_lossy(&received).contains("last"));
+
+        release_tx.send(()).unwrap();
+        while !received.ends_with(b"0\r\n\r\n") {
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed early");
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert!(String::from_utf8_lossy(&received).contains("last"));
+    }
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let req = Request::builder()
+            .header("connection", "keep-alive, Upgrade")
+            .header("upgrade", "WebSocket")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade_request(&req));
+
+        let req = Request::builder().header("upgrade", "websocket").body(()).unwrap();
+        assert!(!is_upgrade_request(&req));
+
+        let req = Request::builder().header("connection", "keep-alive").body(()).unwrap();
+        assert!(!is_upgrade_request(&req));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_is_tunnelled() {
+        // Backend accepts the upgrade, then echoes everything back
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                head.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n")
+                .await
+                .unwrap();
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+
+        let proxy = spawn_test_proxy("ws.localhost", backend_port).await;
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+
+        // Headers well past the old 4 KB peek window, with mixed-case Upgrade value
+        let cookie = "a".repeat(8192);
+        let request = format!(
+            "GET /ws HTTP/1.1\r\nHost: ws.localhost\r\nCookie: {}\r\nConnection: Upgrade\r\nUpgrade: WebSocket\r\n\r\n",
+            cookie
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let n = client.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 101"));
+
+        client.write_all(b"ping").await.unwrap();
+        let n = tokio::time::timeout(std::time::Duration::from_secs(5), client.read(&mut buf))
+            .await
+            .expect("no data through upgraded tunnel")
+            .unwrap();
+        assert_eq!(&buf[..n], b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_tls_path_tunnels_upgrades() {
+        // Backend accepts the upgrade, then echoes everything back
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n")
+                .await
+                .unwrap();
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+
+        // handle_connection is generic over the stream, so plain TCP stands in for TLS
+        let registry = test_registry("ws-tls.localhost", backend_port);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, addr) = listener.accept().await.unwrap();
+            let client = ClientInfo {
+                addr,
+                port: 443,
+                tls: true,
+            };
+            let _ = handle_connection(stream, registry, client).await;
+        });
+
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(b"GET /ws HTTP/1.1\r\nHost: ws-tls.localhost\r\nConnection: Upgrade\r\nUpgrade: WebSocket\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 1024];
+        let n = client.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 101"));
+
+        client.write_all(b"ping").await.unwrap();
+        let n = tokio::time::timeout(std::time::Duration::from_secs(5), client.read(&mut buf))
+            .await
+            .expect("no data through upgraded tunnel")
+            .unwrap();
+        assert_eq!(&buf[..n], b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_routes_each_request() {
+        // Two backends that each answer with their own name
+        async fn spawn_named_backend(name: &'static str) -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    name.len(),
+                    name
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+            port
+        }
+        let one = spawn_named_backend("one").await;
+        let two = spawn_named_backend("two").await;
+
+        let registry = test_registry("one.localhost", one);
+        registry.write().await.register(Service {
+            domain: "two.localhost".to_string(),
+            port: two,
+            pid: std::process::id(),
+            directory: PathBuf::from("/test"),
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let _ = handle_connection(stream, registry, test_client(peer)).await;
+        });
+
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        let mut buf = [0u8; 1024];
+        for name in ["one", "two"] {
+            let request = format!("GET / HTTP/1.1\r\nHost: {}.localhost\r\n\r\n", name);
+            client.write_all(request.as_bytes()).await.unwrap();
+            let n = client.read(&mut buf).await.unwrap();
+            let response = String::from_utf8_lossy(&buf[..n]);
+            assert!(response.ends_with(name), "unexpected response: {}", response);
+        }
+    }
+
+    #[test]
+    fn test_to_http1_request_rewrites_h2() {
+        let req = Request::builder()
+            .version(Version::HTTP_2)
+            .uri("https://app.localhost/assets/main.js?v=1")
+            .header("cookie", "a=1")
+            .header("cookie", "b=2")
+            .body(())
+            .unwrap();
+
+        let req = to_http1_request(req);
+        assert_eq!(req.version(), Version::HTTP_11);
+        assert_eq!(req.uri(), "/assets/main.js?v=1");
+        assert_eq!(req.headers()["host"], "app.localhost");
+        assert_eq!(req.headers()["cookie"], "a=1; b=2");
+    }
+
+    #[test]
+    fn test_to_http1_request_leaves_http1_untouched() {
+        let req = Request::builder()
+            .uri("/path")
+            .header("host", "app.localhost")
+            .body(())
+            .unwrap();
+
+        let req = to_http1_request(req);
+        assert_eq!(req.version(), Version::HTTP_11);
+        assert_eq!(req.uri(), "/path");
+    }
+
+    #[tokio::test]
+    async fn test_h2_connection_is_proxied_to_http1_backend() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).to_string();
+            assert!(head.starts_with("GET /hello HTTP/1.1"), "got: {}", head);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi")
+                .await
+                .unwrap();
+        });
+
+        // handle_h2_connection is generic over the stream, so plain TCP stands in for TLS
+        let registry = test_registry("h2.localhost", backend_port);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let client = ClientInfo {
+                tls: true,
+                ..test_client(peer)
+            };
+            let _ = handle_h2_connection(stream, registry, client).await;
+        });
+
+        let io = TokioIo::new(TcpStream::connect(proxy).await.unwrap());
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), io)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let req = Request::builder()
+            .uri("https://h2.localhost/hello")
+            .body(http_body_util::Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"hi");
+    }
+
+    #[test]
+    fn test_use_h2c() {
+        let grpc = Request::builder()
+            .header("content-type", "application/grpc+proto")
+            .body(())
+            .unwrap();
+        let plain = Request::builder()
+            .header("content-type", "application/json")
+            .body(())
+            .unwrap();
+
+        assert!(use_h2c(&grpc, UpstreamProtocol::Auto));
+        assert!(!use_h2c(&plain, UpstreamProtocol::Auto));
+        assert!(use_h2c(&plain, UpstreamProtocol::H2c));
+        assert!(!use_h2c(&grpc, UpstreamProtocol::Http1));
+    }
+
+    #[test]
+    fn test_to_h2_request_sets_authority() {
+        let req = Request::builder()
+            .uri("/pkg.Service/Method")
+            .header("host", "grpc.localhost")
+            .body(())
+            .unwrap();
+
+        let req = to_h2_request(req);
+        assert_eq!(req.version(), Version::HTTP_2);
+        assert_eq!(req.uri(), "http://grpc.localhost/pkg.Service/Method");
+        assert!(!req.headers().contains_key("host"));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_over_h2c_preserves_trailers() {
+        use http_body_util::Empty;
+
+        // h2c backend replying with a body and gRPC status trailers
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = backend.accept().await.unwrap();
+            let service = service_fn(|req: Request<Incoming>| async move {
+                assert_eq!(req.version(), Version::HTTP_2);
+                let mut trailers = hyper::HeaderMap::new();
+                trailers.insert("grpc-status", "0".parse().unwrap());
+                let body = Full::new(Bytes::from_static(b"reply"))
+                    .with_trailers(async move { Some(Ok::<_, std::convert::Infallible>(trailers)) });
+                Ok::<_, std::convert::Infallible>(Response::new(body))
+            });
+            let _ = http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        // gRPC client talking h2c to the plain HTTP listener
+        let proxy = spawn_test_proxy("grpc.localhost", backend_port).await;
+        let io = TokioIo::new(TcpStream::connect(proxy).await.unwrap());
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), io)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://grpc.localhost/pkg.Service/Method")
+            .header("content-type", "application/grpc")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let collected = response.into_body().collect().await.unwrap();
+        let trailers = collected.trailers().cloned().expect("trailers were dropped");
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(&collected.to_bytes()[..], b"reply");
+    }
+
+    #[test]
+    fn test_add_forwarded_headers() {
+        let mut req = Request::builder()
+            .header("host", "app.localhost")
+            .header("x-forwarded-proto", "spoofed")
+            .body(())
+            .unwrap();
+        let client = ClientInfo {
+            addr: "127.0.0.1:52000".parse().unwrap(),
+            port: 443,
+            tls: true,
+        };
+
+        add_forwarded_headers(&mut req, &client);
+        let headers = req.headers();
+        assert_eq!(headers["x-forwarded-for"], "127.0.0.1");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert_eq!(headers["x-forwarded-host"], "app.localhost");
+        assert_eq!(headers["x-forwarded-port"], "443");
+        assert_eq!(
+            headers["forwarded"],
+            "for=127.0.0.1;proto=https;host=\"app.localhost\""
+        );
+    }
+
+    #[test]
+    fn test_add_forwarded_headers_appends_to_chain() {
+        let mut req = Request::builder()
+            .header("host", "app.localhost")
+            .header("x-forwarded-for", "10.0.0.1")
+            .header("forwarded", "for=10.0.0.1")
+            .body(())
+            .unwrap();
+        let client = ClientInfo {
+            addr: "[::1]:52000".parse().unwrap(),
+            port: 80,
+            tls: false,
+        };
+
+        add_forwarded_headers(&mut req, &client);
+        let headers = req.headers();
+        assert_eq!(headers["x-forwarded-for"], "10.0.0.1, ::1");
+        assert_eq!(
+            headers["forwarded"],
+            "for=10.0.0.1, for=\"[::1]\";proto=http;host=\"app.localhost\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_headers_can_be_disabled() {
+        // Backend echoes the request head back as the body
+        async fn spawn_echo_backend() -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 2048];
+                let n = stream.read(&mut buf).await.unwrap();
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", n);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.write_all(&buf[..n]).await.unwrap();
+            });
+            port
+        }
+
+        for forwarded_headers in [true, false] {
+            let port = spawn_echo_backend().await;
+            let registry = test_registry("fwd.localhost", port);
+            {
+                let mut reg = registry.write().await;
+                let mut service = reg.get("fwd.localhost").cloned().unwrap();
+                service.options.forwarded_headers = forwarded_headers;
+                reg.register(service);
+            }
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let proxy = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (stream, peer) = listener.accept().await.unwrap();
+                let _ = handle_connection(stream, registry, test_client(peer)).await;
+            });
+
+            let mut client = TcpStream::connect(proxy).await.unwrap();
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: fwd.localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0u8; 4096];
+            let n = client.read(&mut buf).await.unwrap();
+            let echoed = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            assert_eq!(echoed.contains("x-forwarded-proto: http"), forwarded_headers);
+            assert_eq!(echoed.contains("forwarded: for=127.0.0.1"), forwarded_headers);
+        }
+    }
+
+    #[test]
+    fn test_strip_path_prefix() {
+        let mut req = Request::builder().uri("/api/users?page=2").body(()).unwrap();
+        strip_path_prefix(&mut req, "/api");
+        assert_eq!(req.uri(), "/users?page=2");
+
+        let mut req = Request::builder().uri("/api").body(()).unwrap();
+        strip_path_prefix(&mut req, "/api");
+        assert_eq!(req.uri(), "/");
+
+        let mut req = Request::builder()
+            .uri("https://app.localhost/api/x")
+            .body(())
+            .unwrap();
+        strip_path_prefix(&mut req, "/api");
+        assert_eq!(req.uri(), "https://app.localhost/x");
+    }
+
+    #[test]
+    fn test_render_dashboard_shows_routes() {
+        let services = vec![Service {
+            domain: "app.localhost".to_string(),
+            port: 4000,
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/web"),
+            options: crate::types::ServiceOptions {
+                routes: vec![crate::types::RouteRule {
+                    path: "/api".to_string(),
+                    service: "api.localhost".to_string(),
+                    strip_prefix: true,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+        let html = render_dashboard(&services, "http", 80);
+        assert!(html.contains("/api -> api.localhost"));
+    }
+
+    #[tokio::test]
+    async fn test_path_route_strips_prefix() {
+        // API backend echoes the request line back
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 2048];
+            let n = stream.read(&mut buf).await.unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).to_string();
+            let line = head.lines().next().unwrap().to_string();
+            let prefix = head
+                .lines()
+                .find(|l| l.to_lowercase().starts_with("x-forwarded-prefix"))
+                .unwrap_or("")
+                .to_string();
+            let body = format!("{}|{}", line, prefix);
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let registry = test_registry("api.localhost", api_port);
+        registry.write().await.register(Service {
+            domain: "app.localhost".to_string(),
+            port: 1, // frontend itself is never hit in this test
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/web"),
+            options: crate::types::ServiceOptions {
+                routes: vec![crate::types::RouteRule {
+                    path: "/api".to_string(),
+                    service: "api.localhost".to_string(),
+                    strip_prefix: true,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let _ = handle_connection(stream, registry, test_client(peer)).await;
+        });
+
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client
+            .write_all(b"GET /api/users?id=1 HTTP/1.1\r\nHost: app.localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0u8; 2048];
+        let n = client.read(&mut buf).await.unwrap();
+        let response = String::from_utf8_lossy(&buf[..n]);
+        assert!(response.contains("GET /users?id=1 HTTP/1.1"), "got: {}", response);
+        assert!(response.to_lowercase().contains("x-forwarded-prefix: /api"));
+    }
+
+    #[tokio::test]
+    async fn test_run_http_serves_every_listener() {
+        let registry = test_registry("api.localhost", 4000);
+        let mut addrs = vec!["127.0.0.1:0".parse().unwrap()];
+        if TcpListener::bind("[::1]:0").await.is_ok() {
+            addrs.push("[::1]:0".parse().unwrap());
+        }
+        let listeners = bind(&addrs).await.unwrap();
+        let bound: Vec<SocketAddr> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+        tokio::spawn(run_http(registry, listeners));
+
+        for addr in bound {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            let response = String::from_utf8_lossy(&response);
+            // Dashboard links carry the non-default port the client came in on
+            let url = format!("http://api.localhost:{}", addr.port());
+            assert!(response.contains(&url), "missing {} in: {}", url, response);
+        }
+    }
+
+    #[test]
+    fn test_is_connect_error_only_for_refused_connections() {
+        use std::io::{Error, ErrorKind};
+        assert!(is_connect_error(&Error::from(ErrorKind::ConnectionRefused).into()));
+        assert!(!is_connect_error(&Error::from(ErrorKind::ConnectionReset).into()));
+        assert!(!is_connect_error(&Error::from(ErrorKind::BrokenPipe).into()));
+        assert!(!is_connect_error(&anyhow::anyhow!("connection refused")));
+    }
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("api.localhost:8080"), "api.localhost");
+        assert_eq!(strip_port("api.localhost"), "api.localhost");
+        assert_eq!(strip_port("127.0.0.1:80"), "127.0.0.1");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+        assert_eq!(strip_port("::1"), "::1");
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_over_ipv6_loopback() {
+        let Ok(listener) = TcpListener::bind("[::1]:0").await else {
+            return; // No IPv6 here
+        };
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(run_http(test_registry("api.localhost", 4000), vec![listener]));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: [::1]:{}\r\nConnection: close\r\n\r\n",
+            addr.port()
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("<!DOCTYPE html>"));
+    }
+
+    #[tokio::test]
+    async fn test_bind_reports_address_in_use() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let err = bind(&[taken.local_addr().unwrap()]).await.unwrap_err();
+        assert!(err.to_string().contains(&taken.local_addr().unwrap().to_string()));
+    }
+
+    fn kill_request(method: &str, token: Option<&str>, origin: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri("/api/kill/api.localhost")
+            .header(header::HOST, "localhost");
+        if let Some(token) = token {
+            builder = builder.header(DASHBOARD_TOKEN_HEADER, token);
+        }
+        if let Some(origin) = origin {
+            builder = builder.header(header::ORIGIN, origin);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_check_dashboard_action() {
+        let client = test_client("127.0.0.1:50000".parse().unwrap());
+        let token = Some(dashboard_token());
+        let check = |req: Request<()>| check_dashboard_action(&req, "localhost", client);
+
+        assert_eq!(check(kill_request("GET", token, None)), Err("method not allowed"));
+        assert_eq!(check(kill_request("POST", None, None)), Err("invalid token"));
+        assert_eq!(check(kill_request("POST", Some("guess"), None)), Err("invalid token"));
+        assert_eq!(
+            check(kill_request("POST", token, Some("http://evil.example"))),
+            Err("cross-origin request")
+        );
+        assert_eq!(check(kill_request("POST", token, Some("http://localhost"))), Ok(()));
+        assert_eq!(check(kill_request("POST", token, None)), Ok(()));
+    }
+
+    #[test]
+    fn test_dashboard_token_is_stable_and_embedded() {
+        assert_eq!(dashboard_token(), dashboard_token());
+        assert_eq!(dashboard_token().len(), 32);
+        assert!(dashboard_token().bytes().all(|b| b.is_ascii_hexdigit()));
+
+        let html = render_dashboard(&[], "http", 80);
+        let meta = format!(r#"<meta name="unport-token" content="{}">"#, dashboard_token());
+        assert!(html.contains(&meta));
+    }
+
+    /// Send a raw request to a one-connection proxy and return the response
+    async fn raw_request(registry: SharedRegistry, request: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let _ = handle_connection(stream, registry, test_client(peer)).await;
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_kill_api_refuses_get() {
+        let registry = test_registry("api.localhost", 4000);
+        let response = raw_request(
+            registry.clone(),
+            "GET /api/kill/api.localhost HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 403"), "got: {}", response);
+        assert!(registry.read().await.get("api.localhost").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_logs_api_requires_token() {
+        let registry = test_registry("api.localhost", 4000);
+        let request = |token: &str| {
+            format!(
+                "GET /api/logs/api.localhost HTTP/1.1\r\nHost: localhost\r\n\
+                 X-Unport-Token: {}\r\nConnection: close\r\n\r\n",
+                token
+            )
+        };
+
+        let response = raw_request(registry.clone(), request("wrong")).await;
+        assert!(response.starts_with("HTTP/1.1 403"), "got: {}", response);
+
+        let response = raw_request(registry.clone(), request(dashboard_token())).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "got: {}", response);
+        assert!(response.ends_with("[]"));
+    }
+
+    #[tokio::test]
+    async fn test_logs_api_unknown_service() {
+        let registry = test_registry("api.localhost", 4000);
+        let response = raw_request(
+            registry,
+            format!(
+                "GET /api/logs/nope.localhost HTTP/1.1\r\nHost: localhost\r\n\
+                 X-Unport-Token: {}\r\nConnection: close\r\n\r\n",
+                dashboard_token()
+            ),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 404"), "got: {}", response);
+    }
+
+    #[tokio::test]
+    async fn test_kill_api_with_token() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id();
+        // Reap the child as soon as it exits, like `unport start` does
+        let waiter = std::thread::spawn(move || child.wait());
+        let registry = Arc::new(RwLock::new(Registry::new()));
+        registry.write().await.register(Service {
+            domain: "api.localhost".to_string(),
+            port: 4000,
+            pid,
+            directory: PathBuf::from("/test"),
+            ..Default::default()
+        });
+
+        let response = raw_request(
+            registry.clone(),
+            format!(
+                "POST /api/kill/api.localhost HTTP/1.1\r\nHost: localhost\r\n\
+                 Origin: http://localhost\r\nX-Unport-Token: {}\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n",
+                dashboard_token()
+            ),
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200"), "got: {}", response);
+        assert!(registry.read().await.get("api.localhost").is_none());
+        assert!(!waiter.join().unwrap().unwrap().success());
+    }
+
+    #[test]
+    fn test_render_dashboard_shows_crashes() {
+        let services = vec![Service {
+            domain: "flaky.localhost".to_string(),
+            port: 4000,
+            pid: std::process::id(),
+            directory: PathBuf::from("/app/flaky"),
+            crashes: 3,
+            last_exit: Some("exit status: 1".to_string()),
+            ..Default::default()
+        }];
+        let html = render_dashboard(&services, "http", 80);
+        assert!(html.contains(r#"<div class="exits">3 crashes, last exit: exit status: 1</div>"#));
+    }
+
+    /// Registry with one service whose proxy waits at most `start_timeout` seconds
+    fn starting_registry(domain: &str, port: u16, start_timeout: u64) -> SharedRegistry {
+        let mut registry = Registry::new();
+        registry.register(Service {
+            domain: domain.to_string(),
+            port,
+            pid: std::process::id(),
+            directory: PathBuf::from("/test"),
+            options: ServiceOptions {
+                start_timeout,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        Arc::new(RwLock::new(registry))
+    }
+
+    async fn unused_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn test_request_waits_for_starting_service() {
+        let port = unused_port().await;
+        let registry = starting_registry("slow.localhost", port, 10);
+
+        // Backend that only starts listening after the request arrived
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nready")
+                .await;
+        });
+
+        let response = raw_request(
+            registry,
+            "GET / HTTP/1.1\r\nHost: slow.localhost\r\nConnection: close\r\n\r\n".to_string(),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "got: {}", response);
+        assert!(response.ends_with("ready"));
+    }
+
+    #[tokio::test]
+    async fn test_service_that_never_listens_gets_starting_page() {
+        let port = unused_port().await;
+        let registry = starting_registry("never.localhost", port, 1);
+
+        let started = std::time::Instant::now();
+        let response = raw_request(
+            registry,
+            "GET / HTTP/1.1\r\nHost: never.localhost\r\nConnection: close\r\n\r\n".to_string(),
+        )
+        .await;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(900));
+        assert!(response.starts_with("HTTP/1.1 503"), "got: {}", response);
+        assert!(response.contains("text/html"));
+        assert!(response.contains(r#"<meta http-equiv="refresh""#));
+        assert!(response.contains("never.localhost"));
+    }
+
+    #[tokio::test]
+    async fn test_refused_connection_gets_starting_page() {
+        // Past its start timeout, a refused connection is answered right away
+        let port = unused_port().await;
+        let registry = starting_registry("down.localhost", port, 0);
+
+        let response = raw_request(
+            registry,
+            "GET / HTTP/1.1\r\nHost: down.localhost\r\nConnection: close\r\n\r\n".to_string(),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 503"), "got: {}", response);
+        assert!(response.contains("Waiting for it to listen on port"));
+    }
+
+    #[test]
+    fn test_render_starting_page_for_dead_process() {
+        let service = Service {
+            domain: "dead.localhost".to_string(),
+            port: 4000,
+            pid: 4000000,
+            last_exit: Some("exit status: 1".to_string()),
+            crashes: 1,
+            ..Default::default()
+        };
+        let html = render_starting_page(&service);
+        assert!(html.contains("status-stopped"));
+        assert!(html.contains("The process isn't running."));
+        assert!(html.contains("1 crash, last exit: exit status: 1"));
+    }
 }
@@ -106,24 +106,8 @@ pub fn generate_cert(domains: &[String]) -> Result<()> {
     // Generate server key pair
     let server_key_pair = KeyPair::generate().context("Failed to generate server key pair")?;
 
-    // Build SANs list
-    // Note: *.localhost wildcard doesn't work in OpenSSL/LibreSSL because it requires
-    // at least 2 dots after the wildcard (e.g., *.example.com works, *.localhost doesn't)
-    // So we add explicit domain SANs for each registered service
-    let mut sans: Vec<SanType> = vec![
-        SanType::DnsName("localhost".try_into().unwrap()),
-        SanType::IpAddress(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1))),
-    ];
-
-    // Add each domain explicitly
-    for domain in domains {
-        if let Ok(name) = domain.as_str().try_into() {
-            sans.push(SanType::DnsName(name));
-        }
-    }
-
     let mut params = CertificateParams::default();
-    params.subject_alt_names = sans;
+    params.subject_alt_names = subject_alt_names(domains);
     params
         .distinguished_name
         .push(DnType::CommonName, "localhost");
@@ -149,6 +133,27 @@ pub fn generate_cert(domains: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Build the SANs list for the server certificate
+///
+/// Note: *.localhost wildcard doesn't work in OpenSSL/LibreSSL because it requires
+/// at least 2 dots after the wildcard (e.g., *.example.com works, *.localhost doesn't)
+/// So we add explicit domain SANs for each registered service. Wildcard services
+/// pass "*.myapp.localhost", which has enough labels to be accepted.
+fn subject_alt_names(domains: &[String]) -> Vec<SanType> {
+    let mut sans: Vec<SanType> = vec![
+        SanType::DnsName("localhost".try_into().unwrap()),
+        SanType::IpAddress(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1))),
+    ];
+
+    // Add each domain explicitly
+    for domain in domains {
+        if let Ok(name) = domain.as_str().try_into() {
+            sans.push(SanType::DnsName(name));
+        }
+    }
+    sans
+}
+
 /// Ensure a basic cert exists (for initial startup)
 pub fn ensure_cert() -> Result<()> {
     let key_path = localhost_key_path();
@@ -189,14 +194,26 @@ pub fn load_tls_config() -> Result<TlsAcceptor> {
         .context("Failed to parse private key")?
         .context("No private key found")?;
 
-    // Build TLS config
-    let config = ServerConfig::builder()
+    let config = build_server_config(cert_chain, key)?;
+
+    log_info!("TLS configuration loaded");
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Build the server TLS config, advertising HTTP/2 and HTTP/1.1 via ALPN
+fn build_server_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<ServerConfig> {
+    let mut config = ServerConfig::builder()
         .with_no_client_auth()
         .with_single_cert(cert_chain, key)
         .context("Failed to build TLS config")?;
 
-    log_info!("TLS configuration loaded");
-    Ok(TlsAcceptor::from(Arc::new(config)))
+    // Prefer h2 so browsers can multiplex requests over a single connection
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
 }
 
 /// Initialize TLS (ensure CA and cert exist, return acceptor)
@@ -375,7 +392,7 @@ fn try_add_to_firefox_nss(ca_path: &std::path::Path) {
         return;
     }
 
-    let home = dirs::home_dir().unwrap_or_default();
+    let home = crate::privileges::home_dir().unwrap_or_default();
     let firefox_dir = home.join(".mozilla/firefox");
 
     if !firefox_dir.exists() {
@@ -505,6 +522,23 @@ mod tests {
         assert!(cert_content.contains("BEGIN CERTIFICATE"));
     }
 
+    #[test]
+    fn test_server_config_advertises_h2_and_http1() {
+        let key_pair = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::default();
+        params.subject_alt_names = vec![SanType::DnsName("localhost".try_into().unwrap())];
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        let cert_chain = vec![cert.der().clone()];
+        let key = PrivateKeyDer::try_from(key_pair.serialize_der()).unwrap();
+        let config = build_server_config(cert_chain, key).unwrap();
+
+        assert_eq!(
+            config.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+    }
+
     #[test]
     fn test_generate_cert_with_explicit_domains() {
         let dir = tempdir().unwrap();
@@ -1029,4 +1063,46 @@ mod tests {
         let cert_pem = server_cert.pem();
         assert!(cert_pem.contains("BEGIN CERTIFICATE"));
     }
+
+    #[test]
+    fn test_wildcard_san_for_wildcard_service() {
+        let ca_key_pair = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::default();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key_pair).unwrap();
+
+        let server_key_pair = KeyPair::generate().unwrap();
+        let domains = vec!["myapp.localhost".to_string(), "*.myapp.localhost".to_string()];
+        let mut params = CertificateParams::default();
+        params.subject_alt_names = subject_alt_names(&domains);
+
+        let server_cert = params
+            .signed_by(&server_key_pair, &ca_cert, &ca_key_pair)
+            .unwrap();
+
+        let pem = parse_pem(&server_cert.pem()).unwrap();
+        let (_, cert) = X509Certificate::from_der(pem.contents()).unwrap();
+
+        let san = match cert
+            .extensions()
+            .iter()
+            .find(|ext| ext.oid == x509_parser::oid_registry::OID_X509_EXT_SUBJECT_ALT_NAME)
+            .expect("Certificate should have SAN extension")
+            .parsed_extension()
+        {
+            ParsedExtension::SubjectAlternativeName(san) => san,
+            _ => panic!("Expected SubjectAlternativeName"),
+        };
+
+        let dns_names: Vec<&str> = san
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(*dns),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(dns_names, vec!["localhost", "myapp.localhost", "*.myapp.localhost"]);
+    }
 }
@@ -1,13 +1,280 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 /// A registered service in the daemon
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct Service {
     pub domain: String,
     pub port: u16,
     pub pid: u32,
+    /// What tells the process apart from a later one reusing its PID
+    /// (None for older registries, or where it can't be read)
+    #[serde(default)]
+    pub identity: Option<ProcessIdentity>,
     pub directory: PathBuf,
+    /// Per-service proxy settings from unport.json
+    #[serde(default)]
+    pub options: ServiceOptions,
+    /// UID of the user who registered the service (None for older registries)
+    #[serde(default)]
+    pub owner: Option<u32>,
+    /// Number of times the process exited unsuccessfully
+    #[serde(default)]
+    pub crashes: u32,
+    /// How the process last exited (e.g., "exit status: 1")
+    #[serde(default)]
+    pub last_exit: Option<String>,
+    /// Result of the health check (None when the service has none)
+    #[serde(default)]
+    pub health: Option<Health>,
+}
+
+impl Service {
+    /// Names the TLS certificate must cover for this service
+    pub fn cert_names(&self) -> Vec<String> {
+        let mut names = vec![self.domain.clone()];
+        if self.options.wildcard {
+            names.push(format!("*.{}", self.domain));
+        }
+        names
+    }
+
+    /// Status shown in `unport list` and the dashboard
+    pub fn status(&self, alive: bool) -> &'static str {
+        match (alive, self.health) {
+            (false, _) => "stopped",
+            (true, None) => "running",
+            (true, Some(health)) => health.as_str(),
+        }
+    }
+
+    /// Crash count and last exit status, once the process has exited at least once
+    pub fn exit_summary(&self) -> Option<String> {
+        let last_exit = self.last_exit.as_ref()?;
+        Some(match self.crashes {
+            0 => format!("last exit: {}", last_exit),
+            1 => format!("1 crash, last exit: {}", last_exit),
+            n => format!("{} crashes, last exit: {}", n, last_exit),
+        })
+    }
+}
+
+/// Port a domain had the last time it ran from a directory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PortPreference {
+    pub domain: String,
+    pub directory: PathBuf,
+    pub port: u16,
+}
+
+/// Identity of a process beyond its PID, from `/proc/<pid>`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessIdentity {
+    /// Clock ticks after boot when the process started
+    pub start_time: u64,
+    /// Executable the process ran when registered (None if it can't be read)
+    ///
+    /// Only informational: it changes when the process `exec`s.
+    #[serde(default)]
+    pub exe: Option<PathBuf>,
+}
+
+/// Per-service proxy settings, declared in unport.json and sent on Register
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServiceOptions {
+    /// Protocol used to talk to the backend
+    #[serde(default)]
+    pub upstream: UpstreamProtocol,
+    /// Add X-Forwarded-* and Forwarded headers to proxied requests
+    #[serde(default = "default_true")]
+    pub forwarded_headers: bool,
+    /// Path prefixes under this domain that are served by other services
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+    /// Also serve subdomains (e.g., tenant.myapp.localhost for myapp.localhost)
+    #[serde(default)]
+    pub wildcard: bool,
+    /// When the daemon starts a background service again after it exits
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Restarts in a row before giving up
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// HTTP probe that tells whether the app is ready
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// Seconds the proxy holds requests while a new process starts listening
+    #[serde(default = "default_start_timeout")]
+    pub start_timeout: u64,
+    /// Seconds between SIGTERM and SIGKILL when the service is stopped
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: u64,
+    /// Move the route to the port the app actually listens on, if that's
+    /// not the one it was given
+    #[serde(default)]
+    pub auto_port: bool,
+}
+
+impl Default for ServiceOptions {
+    fn default() -> Self {
+        Self {
+            upstream: UpstreamProtocol::default(),
+            forwarded_headers: true,
+            routes: Vec::new(),
+            wildcard: false,
+            restart: RestartPolicy::default(),
+            max_retries: default_max_retries(),
+            health_check: None,
+            start_timeout: default_start_timeout(),
+            stop_timeout: default_stop_timeout(),
+            auto_port: false,
+        }
+    }
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_start_timeout() -> u64 {
+    30
+}
+
+fn default_stop_timeout() -> u64 {
+    10
+}
+
+/// Restart policy for background services
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave the service stopped
+    #[default]
+    Never,
+    /// Restart after a non-zero exit or a signal
+    OnFailure,
+    /// Restart whenever the process exits
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether a process that exited should be started again
+    pub fn should_restart(self, success: bool) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+/// HTTP health check for a service, declared as `healthCheck` in unport.json
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthCheck {
+    /// Path to request (e.g., "/health")
+    pub path: String,
+    /// Seconds between probes
+    #[serde(default = "default_health_interval")]
+    pub interval: u64,
+    /// Seconds to wait for a response
+    #[serde(default = "default_health_timeout")]
+    pub timeout: u64,
+    /// Status code a healthy app answers with
+    #[serde(default = "default_expected_status", rename = "expectedStatus")]
+    pub expected_status: u16,
+    /// Seconds a new process may fail probes while it's still starting
+    #[serde(default = "default_start_period", rename = "startPeriod")]
+    pub start_period: u64,
+}
+
+fn default_health_interval() -> u64 {
+    5
+}
+
+fn default_health_timeout() -> u64 {
+    2
+}
+
+fn default_expected_status() -> u16 {
+    200
+}
+
+fn default_start_period() -> u64 {
+    60
+}
+
+/// Health of a service with a health check
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// No successful probe yet, still within the start period
+    Starting,
+    /// The last probe succeeded
+    Healthy,
+    /// Probes fail after the app was healthy or the start period ran out
+    Unhealthy,
+}
+
+impl Health {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Health::Starting => "starting",
+            Health::Healthy => "healthy",
+            Health::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Sends requests under a path prefix to another service
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouteRule {
+    /// Path prefix to match (e.g., "/api")
+    pub path: String,
+    /// Domain of the service that handles matching requests
+    pub service: String,
+    /// Remove the prefix before forwarding ("/api/users" becomes "/users")
+    #[serde(default, rename = "stripPrefix")]
+    pub strip_prefix: bool,
+}
+
+impl std::fmt::Display for RouteRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.path, self.service)?;
+        if self.strip_prefix {
+            write!(f, " (strip prefix)")?;
+        }
+        Ok(())
+    }
+}
+
+impl RouteRule {
+    /// Check whether a request path falls under this rule's prefix
+    pub fn matches(&self, path: &str) -> bool {
+        let prefix = self.path.trim_end_matches('/');
+        match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Protocol the proxy speaks to a backend
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamProtocol {
+    /// Always HTTP/1.1
+    Http1,
+    /// Always cleartext HTTP/2 with prior knowledge
+    H2c,
+    /// HTTP/2 for gRPC requests, HTTP/1.1 for everything else
+    #[default]
+    Auto,
 }
 
 /// Messages sent from CLI to daemon via Unix socket
@@ -19,19 +286,77 @@ pub enum Request {
         port: u16,
         pid: u32,
         directory: PathBuf,
+        #[serde(default)]
+        options: ServiceOptions,
     },
     /// Unregister a service
     Unregister { domain: String },
-    /// Get an available port
-    GetPort,
+    /// Lease a port to a domain started from a directory until it registers
+    GetPort {
+        domain: String,
+        directory: PathBuf,
+        /// Port pinned in unport.json
+        #[serde(default)]
+        port: Option<u16>,
+    },
     /// List all services
     List,
     /// Stop a service by domain
     Stop { domain: String },
     /// Shutdown the daemon
     Shutdown,
+    /// Re-read the daemon config file
+    Reload,
     /// Check if HTTPS is enabled
     HttpsStatus,
+    /// Get the addresses the proxy listens on
+    Listeners,
+    /// Start an app as a background service owned by the daemon
+    Spawn {
+        domain: String,
+        port: u16,
+        directory: PathBuf,
+        command: AppCommand,
+        #[serde(default)]
+        options: ServiceOptions,
+    },
+    /// Get a service's recent output, optionally followed by new lines
+    Logs {
+        domain: String,
+        #[serde(default)]
+        follow: bool,
+        /// Only lines from this Unix time (milliseconds) on
+        #[serde(default)]
+        since: Option<u64>,
+    },
+    /// Add output of a foreground service to its log
+    AppendLogs { domain: String, lines: Vec<LogLine> },
+}
+
+/// Which output stream a log line came from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of a service's output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogLine {
+    /// Unix time in milliseconds
+    pub time: u64,
+    pub stream: LogStream,
+    pub text: String,
+}
+
+/// A resolved app command, ready to run
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AppCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Environment variables set for the app
+    pub env: BTreeMap<String, String>,
 }
 
 /// Messages sent from daemon to CLI
@@ -47,6 +372,131 @@ pub enum Response {
     Error(String),
     /// HTTPS status (enabled/disabled)
     HttpsEnabled(bool),
+    /// Addresses the proxy listens on
+    Listeners(ListenConfig),
+    /// Lines of service output
+    Logs(Vec<LogLine>),
+}
+
+/// Default proxy ports, left out of generated URLs
+pub const DEFAULT_HTTP_PORT: u16 = 80;
+pub const DEFAULT_HTTPS_PORT: u16 = 443;
+
+/// Addresses the daemon's HTTP and HTTPS proxies listen on
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ListenConfig {
+    pub http: Vec<SocketAddr>,
+    /// Empty when HTTPS is disabled
+    pub https: Vec<SocketAddr>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            http: vec![SocketAddr::from(([127, 0, 0, 1], DEFAULT_HTTP_PORT))],
+            https: Vec::new(),
+        }
+    }
+}
+
+impl ListenConfig {
+    /// URL of a domain on the first HTTP listener
+    pub fn http_url(&self, domain: &str) -> String {
+        let port = self.http.first().map_or(DEFAULT_HTTP_PORT, |a| a.port());
+        service_url("http", domain, port)
+    }
+
+    /// URL of a domain on the first HTTPS listener, if HTTPS is enabled
+    pub fn https_url(&self, domain: &str) -> Option<String> {
+        self.https
+            .first()
+            .map(|addr| service_url("https", domain, addr.port()))
+    }
+}
+
+/// Listen settings given on the `daemon start` command line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListenArgs {
+    pub https: bool,
+    pub http_addrs: Vec<SocketAddr>,
+    pub https_addrs: Vec<SocketAddr>,
+}
+
+/// Listeners for `daemon start`, falling back to 127.0.0.1:80 and :443
+pub fn listen_config(
+    https: bool,
+    http: Vec<SocketAddr>,
+    https_addrs: Vec<SocketAddr>,
+) -> ListenConfig {
+    let defaults = ListenConfig::default();
+    ListenConfig {
+        http: if http.is_empty() { defaults.http } else { http },
+        https: match (https, https_addrs.is_empty()) {
+            (false, _) => Vec::new(),
+            (true, true) => vec![SocketAddr::from(([127, 0, 0, 1], DEFAULT_HTTPS_PORT))],
+            (true, false) => https_addrs,
+        },
+    }
+}
+
+/// Parse an HTTP listen address ("8080", "0.0.0.0", "[::1]:8080", ...)
+pub fn parse_http_addr(value: &str) -> Result<SocketAddr, String> {
+    parse_listen_addr(value, DEFAULT_HTTP_PORT)
+}
+
+/// Parse an HTTPS listen address ("8443", "0.0.0.0", "[::1]:8443", ...)
+pub fn parse_https_addr(value: &str) -> Result<SocketAddr, String> {
+    parse_listen_addr(value, DEFAULT_HTTPS_PORT)
+}
+
+/// Parse a listen address, where a bare port means 127.0.0.1 and a bare IP
+/// gets the default port
+fn parse_listen_addr(value: &str, default_port: u16) -> Result<SocketAddr, String> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(port) = value.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    let ip = value.trim_start_matches('[').trim_end_matches(']');
+    match ip.parse::<std::net::IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, default_port)),
+        Err(_) => Err(format!(
+            "invalid listen address '{}' (expected PORT, IP or IP:PORT)",
+            value
+        )),
+    }
+}
+
+/// Check that a domain is hostname-shaped (`[a-z0-9.-]`, no empty labels)
+///
+/// Domains name log files and end up in URLs, so anything that could be a
+/// path (`/`, `..`) is refused.
+pub fn validate_domain(domain: &str) -> Result<(), String> {
+    let valid_chars = domain
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-');
+    if domain.is_empty() || !valid_chars || domain.split('.').any(str::is_empty) {
+        return Err(format!(
+            "Invalid domain '{}' (use lowercase letters, digits, '-' and '.')",
+            domain
+        ));
+    }
+    Ok(())
+}
+
+/// Build a URL for a domain, leaving out the scheme's default port
+pub fn service_url(scheme: &str, domain: &str, port: u16) -> String {
+    let default = if scheme == "https" {
+        DEFAULT_HTTPS_PORT
+    } else {
+        DEFAULT_HTTP_PORT
+    };
+    if port == default {
+        format!("{}://{}", scheme, domain)
+    } else {
+        format!("{}://{}:{}", scheme, domain, port)
+    }
 }
 
 /// Port assignment range
@@ -54,12 +504,19 @@ pub const PORT_RANGE_START: u16 = 4000;
 pub const PORT_RANGE_END: u16 = 5000;
 
 /// Get the unport home directory (~/.unport)
+///
+/// Under sudo this is the invoking user's directory, not root's.
 pub fn unport_dir() -> PathBuf {
-    dirs::home_dir()
+    crate::privileges::home_dir()
         .expect("Could not find home directory")
         .join(".unport")
 }
 
+/// Get the directory for service logs
+pub fn logs_dir() -> PathBuf {
+    unport_dir().join("logs")
+}
+
 /// Get the Unix socket path
 pub fn socket_path() -> PathBuf {
     unport_dir().join("unport.sock")
@@ -70,7 +527,23 @@ pub fn pid_path() -> PathBuf {
     unport_dir().join("unport.pid")
 }
 
+/// Name of the registry file in the state directory
+pub const REGISTRY_FILE: &str = "registry.json";
+
+/// Name of the file with the ports remembered for each domain
+pub const PORTS_FILE: &str = "ports.json";
+
 /// Get the registry file path
 pub fn registry_path() -> PathBuf {
-    unport_dir().join("registry.json")
+    unport_dir().join(REGISTRY_FILE)
+}
+
+/// Get the daemon config file path
+pub fn config_path() -> PathBuf {
+    unport_dir().join("config.json")
+}
+
+/// Get the path of the ports remembered for each domain
+pub fn ports_path() -> PathBuf {
+    unport_dir().join(PORTS_FILE)
 }
@@ -1,7 +1,12 @@
 // Tests for client module helper functions
 // Note: Most client functions require a running daemon, so we test what we can
 
-use std::time::Duration;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use unport_cli::types::{ListenConfig, Request, Response};
 
 // Test the format_duration function logic (reimplemented for testing)
 fn format_duration(duration: Duration) -> String {
@@ -181,3 +186,99 @@ fn test_client_detection_rails() {
     assert_eq!(result.framework, "Rails");
     assert_eq!(result.port_strategy, PortStrategy::CliFlag("-p".into()));
 }
+
+// Tests for `unport start` against a stand-in daemon
+
+/// Answer control-socket requests under `home` the way the daemon would
+fn spawn_fake_daemon(home: &Path) {
+    let dir = home.join(".unport");
+    fs::create_dir_all(&dir).unwrap();
+    let listener = UnixListener::bind(dir.join("unport.sock")).unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let response = match serde_json::from_str(&line) {
+                        Ok(Request::GetPort { .. }) => Response::Port(4999),
+                        Ok(Request::Listeners) => Response::Listeners(ListenConfig::default()),
+                        _ => Response::Ok(None),
+                    };
+                    let json = serde_json::to_string(&response).unwrap() + "\n";
+                    if writer.write_all(json.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn wait_for_file(path: &Path) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "{:?} never appeared", path);
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_start_forwards_sigterm_to_process_group() {
+    let home = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    spawn_fake_daemon(home.path());
+
+    // The app and a background child both trap SIGTERM and leave a marker
+    let marker = |name: &str| project.path().join(name);
+    let script = format!(
+        r#"trap 'touch {app_term}; exit 42' TERM
+sh -c 'trap "touch {child_term}; exit 0" TERM; touch {child_ready}; while :; do sleep 0.1; done' &
+touch {app_ready}
+while :; do sleep 0.1; done
+"#,
+        app_term = marker("app-term").display(),
+        child_term = marker("child-term").display(),
+        child_ready = marker("child-ready").display(),
+        app_ready = marker("app-ready").display(),
+    );
+    fs::write(project.path().join("app.sh"), script).unwrap();
+    fs::write(
+        project.path().join("unport.json"),
+        r#"{"domain": "signals", "start": "sh app.sh"}"#,
+    )
+    .unwrap();
+
+    let mut cli = Command::new(env!("CARGO_BIN_EXE_unport"))
+        .arg("start")
+        .current_dir(project.path())
+        .env("HOME", home.path())
+        .env_remove("SUDO_UID")
+        .env_remove("SUDO_GID")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_file(&marker("app-ready"));
+    wait_for_file(&marker("child-ready"));
+
+    unsafe {
+        libc::kill(cli.id() as i32, libc::SIGTERM);
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = cli.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = cli.kill();
+            panic!("unport start didn't exit after SIGTERM");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    // The whole group got the signal, and the app's own exit code comes back
+    assert!(marker("app-term").exists());
+    wait_for_file(&marker("child-term"));
+    assert_eq!(status.code(), Some(42));
+}
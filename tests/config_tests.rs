@@ -1,6 +1,8 @@
 use std::fs;
 use tempfile::tempdir;
-use unport_cli::config::Config;
+use std::time::Duration;
+use unport_cli::config::{Config, DaemonConfig};
+use unport_cli::types::{ListenArgs, RestartPolicy};
 
 #[test]
 fn test_load_minimal_config() {
@@ -300,3 +302,283 @@ fn test_config_with_trailing_comma_fails() {
     let result = Config::load(dir.path());
     assert!(result.is_err());
 }
+
+#[test]
+fn test_upstream_protocol() {
+    use unport_cli::types::UpstreamProtocol;
+
+    let dir = tempdir().unwrap();
+    let config_content = r#"{"domain": "grpc", "upstream": "h2c"}"#;
+    fs::write(dir.path().join("unport.json"), config_content).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert_eq!(config.upstream, Some(UpstreamProtocol::H2c));
+    assert_eq!(config.service_options().upstream, UpstreamProtocol::H2c);
+}
+
+#[test]
+fn test_upstream_protocol_defaults_to_auto() {
+    use unport_cli::types::UpstreamProtocol;
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "app"}"#).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert_eq!(config.upstream, None);
+    assert_eq!(config.service_options().upstream, UpstreamProtocol::Auto);
+}
+
+#[test]
+fn test_invalid_upstream_protocol_fails() {
+    let dir = tempdir().unwrap();
+    let config_content = r#"{"domain": "app", "upstream": "http3"}"#;
+    fs::write(dir.path().join("unport.json"), config_content).unwrap();
+
+    assert!(Config::load(dir.path()).is_err());
+}
+
+#[test]
+fn test_forwarded_headers_opt_out() {
+    let dir = tempdir().unwrap();
+    let config_content = r#"{"domain": "app", "forwardedHeaders": false}"#;
+    fs::write(dir.path().join("unport.json"), config_content).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert_eq!(config.forwarded_headers, Some(false));
+    assert!(!config.service_options().forwarded_headers);
+}
+
+#[test]
+fn test_forwarded_headers_enabled_by_default() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "app"}"#).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert!(config.service_options().forwarded_headers);
+}
+
+#[test]
+fn test_routes_are_normalized() {
+    let dir = tempdir().unwrap();
+    let config_content = r#"{
+        "domain": "app",
+        "routes": [
+            {"path": "api/", "service": "api", "stripPrefix": true},
+            {"path": "/auth", "service": "auth.example.localhost"}
+        ]
+    }"#;
+    fs::write(dir.path().join("unport.json"), config_content).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    let routes = config.service_options().routes;
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0].path, "/api");
+    assert_eq!(routes[0].service, "api.localhost");
+    assert!(routes[0].strip_prefix);
+    assert_eq!(routes[1].path, "/auth");
+    assert_eq!(routes[1].service, "auth.example.localhost");
+    assert!(!routes[1].strip_prefix);
+}
+
+#[test]
+fn test_route_missing_service_fails() {
+    let dir = tempdir().unwrap();
+    let config_content = r#"{"domain": "app", "routes": [{"path": "/api"}]}"#;
+    fs::write(dir.path().join("unport.json"), config_content).unwrap();
+
+    assert!(Config::load(dir.path()).is_err());
+}
+
+#[test]
+fn test_wildcard_option() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("unport.json"),
+        r#"{"domain": "myapp", "wildcard": true}"#,
+    )
+    .unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert!(config.service_options().wildcard);
+}
+
+#[test]
+fn test_wildcard_defaults_to_false() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "myapp"}"#).unwrap();
+
+    let config = Config::load(dir.path()).unwrap();
+    assert!(!config.service_options().wildcard);
+}
+
+#[test]
+fn test_restart_policy() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("unport.json"),
+        r#"{"domain": "worker", "restart": "on-failure", "maxRetries": 10}"#,
+    )
+    .unwrap();
+
+    let options = Config::load(dir.path()).unwrap().service_options();
+    assert_eq!(options.restart, RestartPolicy::OnFailure);
+    assert_eq!(options.max_retries, 10);
+}
+
+#[test]
+fn test_restart_policy_defaults() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "worker"}"#).unwrap();
+
+    let options = Config::load(dir.path()).unwrap().service_options();
+    assert_eq!(options.restart, RestartPolicy::Never);
+    assert_eq!(options.max_retries, 5);
+}
+
+#[test]
+fn test_invalid_restart_policy_fails() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("unport.json"),
+        r#"{"domain": "worker", "restart": "sometimes"}"#,
+    )
+    .unwrap();
+
+    assert!(Config::load(dir.path()).is_err());
+}
+
+#[test]
+fn test_health_check() {
+    let dir = tempdir().unwrap();
+    fs::write(
+        dir.path().join("unport.json"),
+        r#"{"domain": "api", "healthCheck": {"path": "healthz", "interval": 10, "timeout": 3, "expectedStatus": 204}}"#,
+    )
+    .unwrap();
+
+    let check = Config::load(dir.path()).unwrap().service_options().health_check.unwrap();
+    assert_eq!(check.path, "/healthz");
+    assert_eq!(check.interval, 10);
+    assert_eq!(check.timeout, 3);
+    assert_eq!(check.expected_status, 204);
+    assert_eq!(check.start_period, 60);
+}
+
+#[test]
+fn test_no_health_check() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api"}"#).unwrap();
+
+    assert!(Config::load(dir.path()).unwrap().service_options().health_check.is_none());
+}
+
+#[test]
+fn test_start_timeout() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api", "startTimeout": 90}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().service_options().start_timeout, 90);
+
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api"}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().service_options().start_timeout, 30);
+}
+
+#[test]
+fn test_stop_timeout() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api", "stopTimeout": 3}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().service_options().stop_timeout, 3);
+
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api"}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().service_options().stop_timeout, 10);
+}
+
+#[test]
+fn test_port_pin() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api", "port": 3000}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().port, Some(3000));
+
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api"}"#).unwrap();
+    assert_eq!(Config::load(dir.path()).unwrap().port, None);
+}
+
+#[test]
+fn test_auto_port() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api", "autoPort": true}"#).unwrap();
+    assert!(Config::load(dir.path()).unwrap().service_options().auto_port);
+
+    fs::write(dir.path().join("unport.json"), r#"{"domain": "api"}"#).unwrap();
+    assert!(!Config::load(dir.path()).unwrap().service_options().auto_port);
+}
+
+#[test]
+fn test_daemon_config_missing_file_uses_defaults() {
+    let dir = tempdir().unwrap();
+    let config = DaemonConfig::load(&dir.path().join("config.json")).unwrap();
+    assert_eq!(config, DaemonConfig::default());
+    assert_eq!(config.port_range(), 4000..=5000);
+    assert_eq!(config.cleanup_interval(), Duration::from_secs(30));
+}
+
+#[test]
+fn test_daemon_config_load() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.json");
+    fs::write(
+        &path,
+        r#"{"portRangeStart": 6000, "portRangeEnd": 6100, "cleanupInterval": 5, "httpAddr": ["8080"], "https": true}"#,
+    )
+    .unwrap();
+
+    let config = DaemonConfig::load(&path).unwrap();
+    assert_eq!(config.port_range(), 6000..=6100);
+    assert_eq!(config.cleanup_interval(), Duration::from_secs(5));
+
+    let listen = config.listen(&ListenArgs::default()).unwrap();
+    assert_eq!(listen.http, vec!["127.0.0.1:8080".parse().unwrap()]);
+    assert_eq!(listen.https, vec!["127.0.0.1:443".parse().unwrap()]);
+}
+
+#[test]
+fn test_daemon_config_command_line_wins() {
+    let config = DaemonConfig {
+        http_addr: vec!["8080".to_string()],
+        ..Default::default()
+    };
+    let args = ListenArgs {
+        http_addrs: vec!["127.0.0.1:9090".parse().unwrap()],
+        ..Default::default()
+    };
+    let listen = config.listen(&args).unwrap();
+    assert_eq!(listen.http, vec!["127.0.0.1:9090".parse().unwrap()]);
+    assert!(listen.https.is_empty());
+}
+
+#[test]
+fn test_daemon_config_invalid() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.json");
+    let invalid = [
+        (r#"{"portRangeStart": 5000, "portRangeEnd": 4000}"#, "portRangeStart"),
+        (r#"{"cleanupInterval": 0}"#, "cleanupInterval"),
+        (r#"{"httpAddr": ["not an address"]}"#, "httpAddr"),
+        (r#"{"httpsAddr": ["8443"]}"#, "httpsAddr"),
+        (r#"{"portRangeStart": "4000"}"#, "Invalid JSON"),
+    ];
+    for (content, expected) in invalid {
+        fs::write(&path, content).unwrap();
+        let err = format!("{:#}", DaemonConfig::load(&path).unwrap_err());
+        assert!(err.contains(expected), "{} gave: {}", content, err);
+    }
+}
+
+#[test]
+fn test_daemon_config_rejects_unknown_keys() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.json");
+    fs::write(&path, r#"{"portRangeStrat": 6000}"#).unwrap();
+
+    let err = format!("{:#}", DaemonConfig::load(&path).unwrap_err());
+    assert!(err.contains("unknown field `portRangeStrat`"), "Got: {}", err);
+}
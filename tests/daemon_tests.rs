@@ -1,7 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use unport_cli::config::DaemonConfig;
 use unport_cli::daemon::Registry;
-use unport_cli::types::Service;
+use unport_cli::types::{RouteRule, Service, ServiceOptions};
 
 // Registry tests that don't depend on file system or environment variables
 
@@ -21,6 +22,7 @@ fn test_registry_register() {
         port: 4000,
         pid: 12345,
         directory: PathBuf::from("/test/api"),
+        ..Default::default()
     };
 
     registry.register(service);
@@ -39,6 +41,7 @@ fn test_registry_get() {
         port: 4000,
         pid: 12345,
         directory: PathBuf::from("/test/api"),
+        ..Default::default()
     };
 
     registry.register(service);
@@ -60,6 +63,7 @@ fn test_registry_unregister() {
         port: 4000,
         pid: 12345,
         directory: PathBuf::from("/test/api"),
+        ..Default::default()
     };
 
     registry.register(service);
@@ -82,8 +86,8 @@ fn test_registry_unregister_nonexistent() {
 fn test_registry_get_port() {
     let mut registry = Registry::new();
 
-    let port1 = registry.get_port();
-    let port2 = registry.get_port();
+    let port1 = registry.get_port().unwrap();
+    let port2 = registry.get_port().unwrap();
 
     assert!(port1 >= 4000);
     assert!(port2 >= 4000);
@@ -100,18 +104,21 @@ fn test_registry_multiple_services() {
             port: 4000,
             pid: 1001,
             directory: PathBuf::from("/test/api"),
+            ..Default::default()
         },
         Service {
             domain: "web.localhost".to_string(),
             port: 4001,
             pid: 1002,
             directory: PathBuf::from("/test/web"),
+            ..Default::default()
         },
         Service {
             domain: "admin.localhost".to_string(),
             port: 4002,
             pid: 1003,
             directory: PathBuf::from("/test/admin"),
+            ..Default::default()
         },
     ];
 
@@ -134,6 +141,7 @@ fn test_registry_replace_service() {
         port: 4000,
         pid: 12345,
         directory: PathBuf::from("/test/api"),
+        ..Default::default()
     };
     registry.register(service1);
 
@@ -142,6 +150,7 @@ fn test_registry_replace_service() {
         port: 4001,
         pid: 12346,
         directory: PathBuf::from("/test/api2"),
+        ..Default::default()
     };
     registry.register(service2);
 
@@ -159,6 +168,7 @@ fn test_registry_cleanup_dead_removes_dead_processes() {
         port: 4000,
         pid: 4000000, // Very high PID that doesn't exist
         directory: PathBuf::from("/test/dead"),
+        ..Default::default()
     };
     registry.register(service);
 
@@ -178,6 +188,7 @@ fn test_registry_cleanup_dead_keeps_alive_processes() {
         port: 4000,
         pid: std::process::id(), // Current process PID
         directory: PathBuf::from("/test/alive"),
+        ..Default::default()
     };
     registry.register(service);
 
@@ -192,7 +203,7 @@ fn test_registry_cleanup_dead_keeps_alive_processes() {
 fn test_registry_get_port_increments() {
     let mut registry = Registry::new();
 
-    let ports: Vec<u16> = (0..5).map(|_| registry.get_port()).collect();
+    let ports: Vec<u16> = (0..5).map(|_| registry.get_port().unwrap()).collect();
 
     // All ports should be unique
     for (i, port) in ports.iter().enumerate() {
@@ -215,6 +226,7 @@ fn test_registry_list_returns_all_services() {
             port: 4000 + i,
             pid: 1000 + i as u32,
             directory: PathBuf::from(format!("/test/service{}", i)),
+            ..Default::default()
         };
         registry.register(service);
     }
@@ -232,12 +244,14 @@ fn test_registry_get_returns_correct_service() {
         port: 4000,
         pid: 1000,
         directory: PathBuf::from("/app/api"),
+        ..Default::default()
     };
     let service2 = Service {
         domain: "web.localhost".to_string(),
         port: 4001,
         pid: 1001,
         directory: PathBuf::from("/app/web"),
+        ..Default::default()
     };
 
     registry.register(service1);
@@ -261,12 +275,14 @@ fn test_registry_unregister_only_removes_target() {
         port: 4000,
         pid: 1000,
         directory: PathBuf::from("/app/api"),
+        ..Default::default()
     };
     let service2 = Service {
         domain: "web.localhost".to_string(),
         port: 4001,
         pid: 1001,
         directory: PathBuf::from("/app/web"),
+        ..Default::default()
     };
 
     registry.register(service1);
@@ -279,6 +295,403 @@ fn test_registry_unregister_only_removes_target() {
     assert!(registry.get("web.localhost").is_some());
 }
 
+#[test]
+fn test_registry_set_port() {
+    let mut registry = Registry::new();
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port: 4000,
+        pid: 1000,
+        directory: PathBuf::from("/app/api"),
+        ..Default::default()
+    });
+
+    assert!(registry.set_port("api.localhost", 3000));
+    assert_eq!(registry.get("api.localhost").unwrap().port, 3000);
+    assert!(!registry.set_port("missing.localhost", 3000));
+}
+
+#[test]
+fn test_set_port_is_remembered_for_next_start() {
+    let mut registry = Registry::new();
+    let dir = Path::new("/app/api");
+    let assigned = registry.assign_port("api.localhost", dir, None).unwrap();
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port: assigned,
+        pid: 1000,
+        directory: dir.to_path_buf(),
+        ..Default::default()
+    });
+
+    // The app turned out to listen elsewhere
+    let actual = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    assert!(registry.set_port("api.localhost", actual));
+    registry.unregister("api.localhost");
+
+    assert_eq!(registry.assign_port("api.localhost", dir, None), Ok(actual));
+}
+
+#[test]
+fn test_assign_port_reuses_previous_port() {
+    let mut registry = Registry::new();
+    let dir = Path::new("/app/api");
+    let port = registry.assign_port("api.localhost", dir, None).unwrap();
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port,
+        pid: 1000,
+        directory: dir.to_path_buf(),
+        ..Default::default()
+    });
+    registry.unregister("api.localhost");
+
+    // Other domains don't get it while it's free
+    let other = registry.assign_port("web.localhost", Path::new("/app/web"), None).unwrap();
+    assert_ne!(other, port);
+
+    assert_eq!(registry.assign_port("api.localhost", dir, None).unwrap(), port);
+    // The same domain from another checkout is another app
+    let elsewhere = registry.assign_port("api.localhost", Path::new("/other/api"), None).unwrap();
+    assert_ne!(elsewhere, port);
+}
+
+#[test]
+fn test_registry_state_stays_in_its_state_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut registry = Registry::with_state_dir(dir.path().to_path_buf());
+    let app = Path::new("/app/api");
+    let port = registry.assign_port("api.localhost", app, None).unwrap();
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port,
+        pid: std::process::id(),
+        directory: app.to_path_buf(),
+        ..Default::default()
+    });
+    assert!(dir.path().join("registry.json").exists());
+    assert!(dir.path().join("ports.json").exists());
+
+    // The port is remembered across daemon restarts
+    let mut reloaded = Registry::load_from(dir.path().to_path_buf());
+    assert!(reloaded.get("api.localhost").is_some());
+    reloaded.unregister("api.localhost");
+    assert_eq!(reloaded.assign_port("api.localhost", app, None), Ok(port));
+}
+
+#[test]
+fn test_assign_port_skips_registered_port() {
+    let mut registry = Registry::new();
+    let dir = Path::new("/app/api");
+    let port = registry.assign_port("api.localhost", dir, None).unwrap();
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port,
+        pid: 1000,
+        directory: dir.to_path_buf(),
+        ..Default::default()
+    });
+
+    // Still registered, e.g. a second checkout of the same app is running
+    assert_ne!(registry.assign_port("api.localhost", Path::new("/other/api"), None).unwrap(), port);
+}
+
+#[test]
+fn test_assign_port_pinned() {
+    let mut registry = Registry::new();
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let dir = Path::new("/app/api");
+    assert_eq!(registry.assign_port("api.localhost", dir, Some(port)), Ok(port));
+
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port,
+        pid: 1000,
+        directory: dir.to_path_buf(),
+        ..Default::default()
+    });
+    let err = registry.assign_port("web.localhost", Path::new("/app/web"), Some(port)).unwrap_err();
+    assert!(err.contains("api.localhost"), "Got: {}", err);
+}
+
+#[test]
+fn test_assign_port_pinned_port_in_use() {
+    let mut registry = Registry::new();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let err = registry.assign_port("api.localhost", Path::new("/app/api"), Some(port)).unwrap_err();
+    assert!(err.contains(&port.to_string()), "Got: {}", err);
+    assert!(err.contains("listening"), "Got: {}", err);
+}
+
+#[test]
+fn test_assign_port_rejects_pin_zero() {
+    let mut registry = Registry::new();
+    let err = registry.assign_port("api.localhost", Path::new("/app/api"), Some(0)).unwrap_err();
+    assert!(err.contains("Port 0"), "Got: {}", err);
+}
+
+#[test]
+fn test_assign_port_pinned_privileged_port() {
+    // Root may bind anything
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+    let mut registry = Registry::new();
+    let err = registry.assign_port("api.localhost", Path::new("/app/api"), Some(81)).unwrap_err();
+    assert!(err.contains("need root"), "Got: {}", err);
+}
+
+#[test]
+fn test_load_with_highest_port_registered() {
+    let dir = tempfile::tempdir().unwrap();
+    let services = serde_json::json!({
+        "pinned.localhost": {
+            "domain": "pinned.localhost",
+            "port": 65535,
+            "pid": 1000,
+            "directory": "/app/pinned"
+        }
+    });
+    std::fs::write(dir.path().join("registry.json"), services.to_string()).unwrap();
+
+    let mut registry = Registry::load_from(dir.path().to_path_buf());
+    assert_eq!(registry.get("pinned.localhost").unwrap().port, 65535);
+    let port = registry.get_port().unwrap();
+    assert!((4000..=5000).contains(&port), "{}", port);
+}
+
+#[test]
+fn test_assign_port_leases_until_register() {
+    let mut registry = Registry::new();
+    let api = registry.assign_port("api.localhost", Path::new("/app/api"), None).unwrap();
+
+    // Not registered yet, but nobody else gets it meanwhile
+    let web = registry.assign_port("web.localhost", Path::new("/app/web"), None).unwrap();
+    assert_ne!(web, api);
+    let err = registry.assign_port("web.localhost", Path::new("/app/web"), Some(api)).unwrap_err();
+    assert!(err.contains("api.localhost"), "Got: {}", err);
+
+    // Asking again keeps the same lease
+    assert_eq!(registry.assign_port("api.localhost", Path::new("/app/api"), None), Ok(api));
+}
+
+#[test]
+fn test_assign_port_fails_when_range_is_exhausted() {
+    let mut registry = Registry::new();
+    registry.configure(&DaemonConfig {
+        port_range_start: 6100,
+        port_range_end: 6102,
+        ..Default::default()
+    });
+
+    for domain in ["a.localhost", "b.localhost", "c.localhost"] {
+        let dir = PathBuf::from("/app").join(domain);
+        registry.assign_port(domain, &dir, None).unwrap();
+    }
+    let err = registry.assign_port("d.localhost", Path::new("/app/d"), None).unwrap_err();
+    assert_eq!(err, "No free port in 6100-6102");
+    assert_eq!(registry.get_port(), None);
+}
+
+#[test]
+fn test_get_port_skips_registered_and_leased_ports() {
+    let mut registry = Registry::new();
+    let leased = registry.assign_port("api.localhost", Path::new("/app/api"), None).unwrap();
+    registry.register(Service {
+        domain: "web.localhost".to_string(),
+        port: leased + 1,
+        pid: 1000,
+        directory: PathBuf::from("/app/web"),
+        ..Default::default()
+    });
+
+    let ports: Vec<u16> = (0..1000).map(|_| registry.get_port().unwrap()).collect();
+    assert!(!ports.contains(&leased));
+    assert!(!ports.contains(&(leased + 1)));
+}
+
+fn routed_registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register(Service {
+        domain: "app.localhost".to_string(),
+        port: 4000,
+        pid: 1000,
+        directory: PathBuf::from("/app/web"),
+        options: ServiceOptions {
+            routes: vec![
+                RouteRule {
+                    path: "/api".to_string(),
+                    service: "api.localhost".to_string(),
+                    strip_prefix: true,
+                },
+                RouteRule {
+                    path: "/api/admin".to_string(),
+                    service: "admin.localhost".to_string(),
+                    strip_prefix: false,
+                },
+                RouteRule {
+                    path: "/docs".to_string(),
+                    service: "docs.localhost".to_string(),
+                    strip_prefix: false,
+                },
+            ],
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    registry.register(Service {
+        domain: "api.localhost".to_string(),
+        port: 4001,
+        pid: 1001,
+        directory: PathBuf::from("/app/api"),
+        ..Default::default()
+    });
+    registry.register(Service {
+        domain: "admin.localhost".to_string(),
+        port: 4002,
+        pid: 1002,
+        directory: PathBuf::from("/app/admin"),
+        ..Default::default()
+    });
+    registry
+}
+
+#[test]
+fn test_resolve_without_route_uses_domain_service() {
+    let registry = routed_registry();
+    let route = registry.resolve("app.localhost", "/index.html").unwrap().unwrap();
+    assert_eq!(route.service.port, 4000);
+    assert_eq!(route.strip_prefix, None);
+}
+
+#[test]
+fn test_resolve_follows_route_with_strip() {
+    let registry = routed_registry();
+    let route = registry.resolve("app.localhost", "/api/users").unwrap().unwrap();
+    assert_eq!(route.service.domain, "api.localhost");
+    assert_eq!(route.strip_prefix, Some("/api".to_string()));
+}
+
+#[test]
+fn test_resolve_prefers_longest_prefix() {
+    let registry = routed_registry();
+    let route = registry.resolve("app.localhost", "/api/admin/users").unwrap().unwrap();
+    assert_eq!(route.service.domain, "admin.localhost");
+    assert_eq!(route.strip_prefix, None);
+}
+
+#[test]
+fn test_resolve_route_matches_whole_segments() {
+    let registry = routed_registry();
+    let route = registry.resolve("app.localhost", "/apiary").unwrap().unwrap();
+    assert_eq!(route.service.domain, "app.localhost");
+}
+
+#[test]
+fn test_resolve_route_to_missing_service() {
+    let registry = routed_registry();
+    let result = registry.resolve("app.localhost", "/docs/intro").unwrap();
+    assert_eq!(result, Err("docs.localhost".to_string()));
+}
+
+#[test]
+fn test_resolve_unknown_domain() {
+    let registry = routed_registry();
+    assert!(registry.resolve("unknown.localhost", "/").is_none());
+}
+
+fn wildcard_registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register(Service {
+        domain: "myapp.localhost".to_string(),
+        port: 5000,
+        pid: 2000,
+        directory: PathBuf::from("/app/tenants"),
+        options: ServiceOptions {
+            wildcard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    registry.register(Service {
+        domain: "eu.myapp.localhost".to_string(),
+        port: 5001,
+        pid: 2001,
+        directory: PathBuf::from("/app/eu"),
+        options: ServiceOptions {
+            wildcard: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    registry.register(Service {
+        domain: "admin.myapp.localhost".to_string(),
+        port: 5002,
+        pid: 2002,
+        directory: PathBuf::from("/app/admin"),
+        ..Default::default()
+    });
+    registry.register(Service {
+        domain: "plain.localhost".to_string(),
+        port: 5003,
+        pid: 2003,
+        directory: PathBuf::from("/app/plain"),
+        ..Default::default()
+    });
+    registry
+}
+
+#[test]
+fn test_lookup_wildcard_subdomain() {
+    let registry = wildcard_registry();
+    assert_eq!(registry.lookup("acme.myapp.localhost").unwrap().port, 5000);
+    assert_eq!(registry.lookup("myapp.localhost").unwrap().port, 5000);
+}
+
+#[test]
+fn test_lookup_most_specific_wins() {
+    let registry = wildcard_registry();
+    // Exact match beats the wildcard
+    assert_eq!(registry.lookup("admin.myapp.localhost").unwrap().port, 5002);
+    // Closer wildcard parent beats the farther one
+    assert_eq!(registry.lookup("acme.eu.myapp.localhost").unwrap().port, 5001);
+    assert_eq!(registry.lookup("x.acme.myapp.localhost").unwrap().port, 5000);
+}
+
+#[test]
+fn test_lookup_requires_wildcard_opt_in() {
+    let registry = wildcard_registry();
+    assert!(registry.lookup("acme.plain.localhost").is_none());
+    assert_eq!(registry.lookup("x.admin.myapp.localhost").unwrap().port, 5000);
+}
+
+#[test]
+fn test_resolve_wildcard_subdomain() {
+    let registry = wildcard_registry();
+    let route = registry.resolve("acme.myapp.localhost", "/").unwrap().unwrap();
+    assert_eq!(route.service.domain, "myapp.localhost");
+}
+
+#[test]
+fn test_https_addr_requires_https_flag() {
+    let output = Command::new(env!("CARGO_BIN_EXE_unport"))
+        .args(["daemon", "start", "--https-addr", "8443"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--https"), "Got: {}", stderr);
+}
+
 /// Test that `unport daemon` without subcommand shows help/error
 #[test]
 fn test_daemon_requires_subcommand() {
@@ -1,5 +1,10 @@
 use unport_cli::detect::PortStrategy;
-use unport_cli::process::spawn_app;
+use std::time::{Duration, Instant};
+use unport_cli::process::{
+    app_command, identify, is_alive, is_same_process, is_service_alive, parse_stat, read_stat,
+    spawn_app, terminate, Termination,
+};
+use unport_cli::types::{ProcessIdentity, Service};
 
 #[test]
 fn test_spawn_app_with_env_var() {
@@ -176,3 +181,223 @@ fn test_spawn_app_arg_override_takes_precedence() {
     let mut child = result.unwrap();
     child.wait().unwrap();
 }
+
+#[test]
+fn test_app_command_env_var() {
+    let app = app_command("npm run dev", 4000, &PortStrategy::EnvVar("PORT".into()), None, None)
+        .unwrap();
+    assert_eq!(app.program, "npm");
+    assert_eq!(app.args, vec!["run", "dev"]);
+    assert_eq!(app.env.get("PORT").map(String::as_str), Some("4000"));
+}
+
+#[test]
+fn test_app_command_cli_flag() {
+    let app = app_command(
+        "python manage.py runserver",
+        4001,
+        &PortStrategy::CliFlag("0.0.0.0:".into()),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(app.args, vec!["manage.py", "runserver", "0.0.0.0:4001"]);
+    assert!(app.env.is_empty());
+}
+
+#[test]
+fn test_app_command_overrides() {
+    let strategy = PortStrategy::CliFlag("--port".into());
+    let app = app_command("app", 4002, &strategy, Some("APP_PORT"), None).unwrap();
+    assert_eq!(app.env.get("APP_PORT").map(String::as_str), Some("4002"));
+    assert!(app.args.is_empty());
+
+    let app = app_command("app", 4002, &strategy, None, Some("-p")).unwrap();
+    assert_eq!(app.args, vec!["-p", "4002"]);
+}
+
+#[test]
+fn test_app_command_empty() {
+    assert!(app_command("  ", 4000, &PortStrategy::EnvVar("PORT".into()), None, None).is_err());
+}
+
+/// Spawn a shell script the way unport spawns apps
+fn spawn_script(dir: &std::path::Path, script: &str) -> std::process::Child {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.join("app.sh");
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    spawn_app(
+        path.to_str().unwrap(),
+        4000,
+        &PortStrategy::EnvVar("PORT".into()),
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_terminate_stops_whole_group() {
+    // The shell's background sleep would outlive a plain kill of the shell
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("sleep.pid");
+    let script = format!("sleep 30 &\necho $! > {}\nwait", pid_file.display());
+    let mut child = spawn_script(dir.path(), &script);
+    let pid = child.id();
+    let waiter = std::thread::spawn(move || child.wait());
+    std::thread::sleep(Duration::from_millis(200));
+
+    let started = Instant::now();
+    assert_eq!(terminate(pid, identify(pid).as_ref(), Duration::from_secs(5)).await, Termination::Exited);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(!waiter.join().unwrap().unwrap().success());
+    // Gone, or a zombie if nothing reaps orphans here
+    let sleep_pid = std::fs::read_to_string(&pid_file).unwrap();
+    let sleep = read_stat(sleep_pid.trim().parse().unwrap());
+    assert!(sleep.is_none_or(|stat| stat.state == 'Z'));
+}
+
+#[tokio::test]
+async fn test_terminate_escalates_to_kill() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut child = spawn_script(dir.path(), "trap '' TERM\nwhile true; do sleep 1; done");
+    let pid = child.id();
+    let waiter = std::thread::spawn(move || child.wait());
+    std::thread::sleep(Duration::from_millis(200));
+
+    assert_eq!(terminate(pid, identify(pid).as_ref(), Duration::from_millis(300)).await, Termination::Killed);
+    assert!(!waiter.join().unwrap().unwrap().success());
+}
+
+#[tokio::test]
+async fn test_terminate_not_running() {
+    assert_eq!(terminate(4000000, None, Duration::from_secs(1)).await, Termination::NotRunning);
+}
+
+#[tokio::test]
+async fn test_terminate_skips_reused_pid() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut child = spawn_script(dir.path(), "sleep 30");
+    let pid = child.id();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // Recorded for a process that started at another time
+    let stale = ProcessIdentity {
+        start_time: 1,
+        exe: None,
+    };
+    assert_eq!(
+        terminate(pid, Some(&stale), Duration::from_secs(1)).await,
+        Termination::NotRunning
+    );
+    assert!(child.try_wait().unwrap().is_none());
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_is_alive() {
+    assert!(is_alive(std::process::id()));
+    // PID 1 always exists, even where we may not signal it
+    assert!(is_alive(1));
+    assert!(!is_alive(4000000));
+}
+
+#[test]
+fn test_identify_current_process() {
+    let identity = identify(std::process::id()).unwrap();
+    assert_eq!(identity.exe, Some(std::env::current_exe().unwrap()));
+    assert!(is_same_process(std::process::id(), Some(&identity)));
+}
+
+#[test]
+fn test_is_same_process_detects_reuse() {
+    let pid = std::process::id();
+    let identity = identify(pid).unwrap();
+
+    let started_later = ProcessIdentity {
+        start_time: identity.start_time + 1,
+        ..identity.clone()
+    };
+    assert!(!is_same_process(pid, Some(&started_later)));
+
+    // A process that exec'd since is still the same one
+    let other_exe = ProcessIdentity {
+        exe: Some("/usr/bin/something-else".into()),
+        ..identity
+    };
+    assert!(is_same_process(pid, Some(&other_exe)));
+}
+
+#[tokio::test]
+async fn test_service_that_execs_stays_alive() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut child = spawn_script(dir.path(), "sleep 0.3\nexec sleep 30");
+    let pid = child.id();
+    let service = Service {
+        domain: "exec.localhost".into(),
+        pid,
+        identity: identify(pid),
+        ..Default::default()
+    };
+    let exe_before = service.identity.as_ref().and_then(|i| i.exe.clone());
+
+    // Wait for the shell to turn into sleep
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while identify(pid).and_then(|i| i.exe) == exe_before {
+        assert!(Instant::now() < deadline, "the script never exec'd");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    assert!(is_service_alive(&service));
+    assert_eq!(
+        terminate(pid, service.identity.as_ref(), Duration::from_secs(2)).await,
+        Termination::Exited
+    );
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_is_same_process_without_identity() {
+    // Older registries have nothing to compare against
+    assert!(is_same_process(std::process::id(), None));
+}
+
+#[test]
+fn test_is_service_alive() {
+    let pid = std::process::id();
+    let mut service = Service {
+        domain: "app.localhost".into(),
+        pid,
+        identity: identify(pid),
+        ..Default::default()
+    };
+    assert!(is_service_alive(&service));
+
+    service.identity = Some(ProcessIdentity {
+        start_time: 1,
+        exe: None,
+    });
+    assert!(!is_service_alive(&service));
+
+    service.pid = 4000000;
+    service.identity = None;
+    assert!(!is_service_alive(&service));
+}
+
+#[test]
+fn test_parse_stat() {
+    let stat = "4242 (my app) S 1 4242 4242 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 987654 1000 50";
+    let parsed = parse_stat(stat).unwrap();
+    assert_eq!(parsed.state, 'S');
+    assert_eq!(parsed.ppid, 1);
+    assert_eq!(parsed.pgrp, 4242);
+    assert_eq!(parsed.start_time, 987654);
+
+    // A ')' in the command name doesn't throw off the fields
+    let stat = "7 (a) b) Z 3 7 7 0 -1 0 0 0 0 0 0 0 0 0 20 0 1 0 55 0 0";
+    assert_eq!(parse_stat(stat).unwrap().state, 'Z');
+    assert!(parse_stat("garbage").is_none());
+}
@@ -295,6 +295,6 @@ fn test_max_port_calculation() {
 fn test_port_range_constants() {
     assert_eq!(PORT_RANGE_START, 4000);
     assert_eq!(PORT_RANGE_END, 5000);
-    assert!(PORT_RANGE_END > PORT_RANGE_START);
+    const { assert!(PORT_RANGE_END > PORT_RANGE_START) };
     assert_eq!(PORT_RANGE_END - PORT_RANGE_START, 1000);
 }
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 use unport_cli::types::{
-    Request, Response, Service, PORT_RANGE_END, PORT_RANGE_START,
-    pid_path, registry_path, socket_path, unport_dir,
+    Request, Response, Service, ServiceOptions, UpstreamProtocol, PORT_RANGE_END, PORT_RANGE_START,
+    logs_dir, pid_path, registry_path, socket_path, unport_dir, AppCommand, Health, HealthCheck, LogLine, LogStream, ProcessIdentity, RestartPolicy,
 };
 
 mod service_tests {
@@ -14,6 +14,7 @@ mod service_tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/home/user/api"),
+            ..Default::default()
         };
 
         assert_eq!(service.domain, "api.localhost");
@@ -22,6 +23,106 @@ mod service_tests {
         assert_eq!(service.directory, PathBuf::from("/home/user/api"));
     }
 
+    #[test]
+    fn test_service_without_owner_deserializes() {
+        // Registries written before owners were recorded
+        let json = r#"{"domain":"api.localhost","port":4000,"pid":1,"directory":"/app"}"#;
+        let service: Service = serde_json::from_str(json).unwrap();
+        assert_eq!(service.owner, None);
+
+        let owned = Service {
+            owner: Some(1000),
+            ..service
+        };
+        let parsed: Service = serde_json::from_str(&serde_json::to_string(&owned).unwrap()).unwrap();
+        assert_eq!(parsed.owner, Some(1000));
+    }
+
+    #[test]
+    fn test_service_identity_round_trip() {
+        let json = r#"{"domain":"api.localhost","port":4000,"pid":1,"directory":"/app"}"#;
+        let service: Service = serde_json::from_str(json).unwrap();
+        assert_eq!(service.identity, None);
+
+        let identified = Service {
+            identity: Some(ProcessIdentity {
+                start_time: 987654,
+                exe: Some(PathBuf::from("/usr/bin/node")),
+            }),
+            ..service
+        };
+        let parsed: Service =
+            serde_json::from_str(&serde_json::to_string(&identified).unwrap()).unwrap();
+        assert_eq!(parsed, identified);
+    }
+
+    #[test]
+    fn test_exit_summary() {
+        let mut service = Service::default();
+        assert_eq!(service.exit_summary(), None);
+
+        service.last_exit = Some("exit status: 0".to_string());
+        assert_eq!(service.exit_summary().unwrap(), "last exit: exit status: 0");
+
+        service.crashes = 1;
+        service.last_exit = Some("signal: 9 (SIGKILL)".to_string());
+        assert_eq!(service.exit_summary().unwrap(), "1 crash, last exit: signal: 9 (SIGKILL)");
+
+        service.crashes = 4;
+        assert_eq!(service.exit_summary().unwrap(), "4 crashes, last exit: signal: 9 (SIGKILL)");
+    }
+
+    #[test]
+    fn test_restart_policy_serialization() {
+        let policy: RestartPolicy = serde_json::from_str("\"on-failure\"").unwrap();
+        assert_eq!(policy, RestartPolicy::OnFailure);
+        assert_eq!(serde_json::to_string(&RestartPolicy::Always).unwrap(), "\"always\"");
+        assert_eq!(RestartPolicy::default(), RestartPolicy::Never);
+    }
+
+    #[test]
+    fn test_status_reflects_health() {
+        let mut service = Service::default();
+        assert_eq!(service.status(false), "stopped");
+        assert_eq!(service.status(true), "running");
+
+        service.health = Some(Health::Starting);
+        assert_eq!(service.status(true), "starting");
+        service.health = Some(Health::Unhealthy);
+        assert_eq!(service.status(true), "unhealthy");
+        assert_eq!(service.status(false), "stopped");
+    }
+
+    #[test]
+    fn test_health_serialization() {
+        assert_eq!(serde_json::to_string(&Health::Healthy).unwrap(), "\"healthy\"");
+
+        let service: Service = serde_json::from_str(
+            r#"{"domain": "api.localhost", "port": 4000, "pid": 1, "directory": "/app", "health": "starting"}"#,
+        )
+        .unwrap();
+        assert_eq!(service.health, Some(Health::Starting));
+    }
+
+    #[test]
+    fn test_health_check_defaults() {
+        let check: HealthCheck = serde_json::from_str(r#"{"path": "/health"}"#).unwrap();
+        assert_eq!(check.interval, 5);
+        assert_eq!(check.timeout, 2);
+        assert_eq!(check.expected_status, 200);
+        assert_eq!(check.start_period, 60);
+    }
+
+    #[test]
+    fn test_restart_policy_decisions() {
+        assert!(!RestartPolicy::Never.should_restart(false));
+        assert!(!RestartPolicy::Never.should_restart(true));
+        assert!(RestartPolicy::OnFailure.should_restart(false));
+        assert!(!RestartPolicy::OnFailure.should_restart(true));
+        assert!(RestartPolicy::Always.should_restart(false));
+        assert!(RestartPolicy::Always.should_restart(true));
+    }
+
     #[test]
     fn test_service_clone() {
         let service = Service {
@@ -29,6 +130,7 @@ mod service_tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/home/user/api"),
+            ..Default::default()
         };
 
         let cloned = service.clone();
@@ -42,6 +144,7 @@ mod service_tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/home/user/api"),
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&service).unwrap();
@@ -72,6 +175,7 @@ mod service_tests {
             port: 4500,
             pid: 99999,
             directory: PathBuf::from("/tmp/test"),
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&service).unwrap();
@@ -90,6 +194,7 @@ mod request_tests {
             port: 4000,
             pid: 12345,
             directory: PathBuf::from("/home/user/api"),
+            options: ServiceOptions::default(),
         };
 
         let json = serde_json::to_string(&req).unwrap();
@@ -110,9 +215,21 @@ mod request_tests {
 
     #[test]
     fn test_getport_request() {
-        let req = Request::GetPort;
+        let req = Request::GetPort {
+            domain: "api.localhost".to_string(),
+            directory: PathBuf::from("/app"),
+            port: Some(3000),
+        };
         let json = serde_json::to_string(&req).unwrap();
-        assert_eq!(json, "\"GetPort\"");
+        assert!(json.contains("GetPort"));
+        assert!(json.contains("3000"));
+
+        // Without a pin
+        let json = r#"{"GetPort":{"domain":"api.localhost","directory":"/app"}}"#;
+        match serde_json::from_str(json).unwrap() {
+            Request::GetPort { port, .. } => assert_eq!(port, None),
+            other => panic!("Unexpected request: {:?}", other),
+        }
     }
 
     #[test]
@@ -155,6 +272,28 @@ mod request_tests {
         }
     }
 
+    #[test]
+    fn test_register_without_options_uses_defaults() {
+        // Older clients don't send options
+        let json = r#"{"Register":{"domain":"api.localhost","port":4000,"pid":12345,"directory":"/home/user/api"}}"#;
+        let req: Request = serde_json::from_str(json).unwrap();
+
+        match req {
+            Request::Register { options, .. } => {
+                assert_eq!(options, ServiceOptions::default());
+                assert_eq!(options.upstream, UpstreamProtocol::Auto);
+            }
+            _ => panic!("Expected Register request"),
+        }
+    }
+
+    #[test]
+    fn test_upstream_protocol_serialization() {
+        assert_eq!(serde_json::to_string(&UpstreamProtocol::Http1).unwrap(), "\"http1\"");
+        assert_eq!(serde_json::to_string(&UpstreamProtocol::H2c).unwrap(), "\"h2c\"");
+        assert_eq!(serde_json::to_string(&UpstreamProtocol::Auto).unwrap(), "\"auto\"");
+    }
+
     #[test]
     fn test_https_status_request() {
         let req = Request::HttpsStatus;
@@ -170,6 +309,85 @@ mod request_tests {
     }
 }
 
+mod logs_request_tests {
+    use super::*;
+
+    #[test]
+    fn test_logs_request_defaults() {
+        let req: Request = serde_json::from_str(r#"{"Logs":{"domain":"api.localhost"}}"#).unwrap();
+        assert_eq!(
+            req,
+            Request::Logs {
+                domain: "api.localhost".to_string(),
+                follow: false,
+                since: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_append_logs_roundtrip() {
+        let req = Request::AppendLogs {
+            domain: "api.localhost".to_string(),
+            lines: vec![LogLine {
+                time: 1_700_000_000_000,
+                stream: LogStream::Stderr,
+                text: "listening".to_string(),
+            }],
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""stream":"stderr""#));
+        let parsed: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, req);
+    }
+
+    #[test]
+    fn test_logs_response_roundtrip() {
+        let resp = Response::Logs(Vec::new());
+        let json = serde_json::to_string(&resp).unwrap();
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), resp);
+    }
+}
+
+mod spawn_request_tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_roundtrip() {
+        let mut command = AppCommand {
+            program: "npm".to_string(),
+            args: vec!["run".to_string(), "dev".to_string()],
+            ..Default::default()
+        };
+        command.env.insert("PORT".to_string(), "4000".to_string());
+
+        let req = Request::Spawn {
+            domain: "web.localhost".to_string(),
+            port: 4000,
+            directory: PathBuf::from("/app/web"),
+            command,
+            options: ServiceOptions::default(),
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains("Spawn"));
+        let parsed: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, req);
+    }
+
+    #[test]
+    fn test_spawn_without_options() {
+        let json = r#"{"Spawn":{"domain":"web.localhost","port":4000,"directory":"/app",
+            "command":{"program":"true","args":[],"env":{}}}}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::Spawn { command, options, .. } => {
+                assert_eq!(command.program, "true");
+                assert_eq!(options, ServiceOptions::default());
+            }
+            other => panic!("Expected Spawn, got {:?}", other),
+        }
+    }
+}
+
 mod response_tests {
     use super::*;
 
@@ -213,12 +431,14 @@ mod response_tests {
                 port: 4000,
                 pid: 1000,
                 directory: PathBuf::from("/app/api"),
+                ..Default::default()
             },
             Service {
                 domain: "web.localhost".to_string(),
                 port: 4001,
                 pid: 1001,
                 directory: PathBuf::from("/app/web"),
+                ..Default::default()
             },
         ];
 
@@ -280,6 +500,66 @@ mod response_tests {
     }
 }
 
+mod route_rule_tests {
+    use super::*;
+    use unport_cli::types::RouteRule;
+
+    fn rule(path: &str) -> RouteRule {
+        RouteRule {
+            path: path.to_string(),
+            service: "api.localhost".to_string(),
+            strip_prefix: false,
+        }
+    }
+
+    #[test]
+    fn test_route_matches_prefix() {
+        assert!(rule("/api").matches("/api"));
+        assert!(rule("/api").matches("/api/"));
+        assert!(rule("/api").matches("/api/users"));
+        assert!(!rule("/api").matches("/apiary"));
+        assert!(!rule("/api").matches("/"));
+    }
+
+    #[test]
+    fn test_route_with_trailing_slash() {
+        assert!(rule("/api/").matches("/api/users"));
+        assert!(rule("/api/").matches("/api"));
+    }
+
+    #[test]
+    fn test_root_route_matches_everything() {
+        assert!(rule("/").matches("/"));
+        assert!(rule("/").matches("/anything"));
+    }
+
+    #[test]
+    fn test_route_deserialize_camel_case() {
+        let json = r#"{"path": "/api", "service": "api.localhost", "stripPrefix": true}"#;
+        let route: RouteRule = serde_json::from_str(json).unwrap();
+        assert!(route.strip_prefix);
+        assert_eq!(route.to_string(), "/api -> api.localhost (strip prefix)");
+    }
+
+    #[test]
+    fn test_service_options_default_has_no_routes() {
+        assert!(ServiceOptions::default().routes.is_empty());
+        assert!(!ServiceOptions::default().wildcard);
+    }
+
+    #[test]
+    fn test_cert_names_include_wildcard() {
+        let mut service = Service {
+            domain: "myapp.localhost".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(service.cert_names(), vec!["myapp.localhost"]);
+
+        service.options.wildcard = true;
+        assert_eq!(service.cert_names(), vec!["myapp.localhost", "*.myapp.localhost"]);
+    }
+}
+
 mod port_range_tests {
     use super::*;
 
@@ -295,7 +575,7 @@ mod port_range_tests {
 
     #[test]
     fn test_port_range_valid() {
-        assert!(PORT_RANGE_END > PORT_RANGE_START);
+        const { assert!(PORT_RANGE_END > PORT_RANGE_START) };
     }
 
     #[test]
@@ -306,7 +586,7 @@ mod port_range_tests {
     #[test]
     fn test_port_in_range() {
         let port = 4500;
-        assert!(port >= PORT_RANGE_START && port <= PORT_RANGE_END);
+        assert!((PORT_RANGE_START..=PORT_RANGE_END).contains(&port));
     }
 
     #[test]
@@ -349,6 +629,12 @@ mod path_tests {
         assert!(path.ends_with("registry.json"));
     }
 
+    #[test]
+    fn test_logs_dir() {
+        assert!(logs_dir().ends_with("logs"));
+        assert!(logs_dir().starts_with(unport_dir()));
+    }
+
     #[test]
     fn test_paths_in_unport_dir() {
         let dir = unport_dir();
@@ -361,3 +647,91 @@ mod path_tests {
         assert!(reg.starts_with(&dir));
     }
 }
+
+mod listen_tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use unport_cli::types::{
+        listen_config, parse_http_addr, parse_https_addr, service_url, ListenConfig,
+    };
+
+    #[test]
+    fn test_service_url_omits_default_ports() {
+        assert_eq!(service_url("http", "api.localhost", 80), "http://api.localhost");
+        assert_eq!(service_url("https", "api.localhost", 443), "https://api.localhost");
+    }
+
+    #[test]
+    fn test_service_url_includes_other_ports() {
+        assert_eq!(service_url("http", "api.localhost", 8080), "http://api.localhost:8080");
+        assert_eq!(service_url("https", "api.localhost", 80), "https://api.localhost:80");
+    }
+
+    #[test]
+    fn test_default_listen_config() {
+        let listen = ListenConfig::default();
+        assert_eq!(listen.http, vec!["127.0.0.1:80".parse::<SocketAddr>().unwrap()]);
+        assert!(listen.https.is_empty());
+        assert_eq!(listen.http_url("api.localhost"), "http://api.localhost");
+        assert_eq!(listen.https_url("api.localhost"), None);
+    }
+
+    #[test]
+    fn test_listen_config_urls_use_first_listener() {
+        let listen = ListenConfig {
+            http: vec!["[::1]:8080".parse().unwrap(), "127.0.0.1:80".parse().unwrap()],
+            https: vec!["127.0.0.1:8443".parse().unwrap()],
+        };
+        assert_eq!(listen.http_url("api.localhost"), "http://api.localhost:8080");
+        assert_eq!(
+            listen.https_url("api.localhost"),
+            Some("https://api.localhost:8443".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_listen_addr_forms() {
+        assert_eq!(parse_http_addr("8080").unwrap().to_string(), "127.0.0.1:8080");
+        assert_eq!(parse_http_addr("0.0.0.0").unwrap().to_string(), "0.0.0.0:80");
+        assert_eq!(parse_https_addr("0.0.0.0").unwrap().to_string(), "0.0.0.0:443");
+        assert_eq!(parse_http_addr("[::1]:8080").unwrap().to_string(), "[::1]:8080");
+        assert_eq!(parse_https_addr("[::1]").unwrap().to_string(), "[::1]:443");
+        assert_eq!(parse_http_addr("::1").unwrap().to_string(), "[::1]:80");
+    }
+
+    #[test]
+    fn test_parse_listen_addr_invalid() {
+        assert!(parse_http_addr("localhost").is_err());
+        assert!(parse_http_addr("127.0.0.1:99999").is_err());
+        assert!(parse_http_addr("").is_err());
+    }
+
+    #[test]
+    fn test_listen_config_defaults() {
+        let listen = listen_config(false, vec![], vec![]);
+        assert_eq!(listen.http, vec!["127.0.0.1:80".parse().unwrap()]);
+        assert!(listen.https.is_empty());
+
+        let listen = listen_config(true, vec![], vec![]);
+        assert_eq!(listen.https, vec!["127.0.0.1:443".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_listen_config_multiple_listeners() {
+        let http = vec!["127.0.0.1:8080".parse().unwrap(), "[::1]:8080".parse().unwrap()];
+        let listen = listen_config(true, http.clone(), vec!["0.0.0.0:8443".parse().unwrap()]);
+        assert_eq!(listen.http, http);
+        assert_eq!(listen.https, vec!["0.0.0.0:8443".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_listeners_roundtrip() {
+        let json = serde_json::to_string(&Request::Listeners).unwrap();
+        assert_eq!(json, "\"Listeners\"");
+
+        let resp = Response::Listeners(ListenConfig::default());
+        let json = serde_json::to_string(&resp).unwrap();
+        let parsed: Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, resp);
+    }
+}